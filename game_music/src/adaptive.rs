@@ -0,0 +1,94 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+/// Things the game can tell the music about. Each is a value from 0 to 1,
+/// and reaches the playing patch as the graph input with the same index, so
+/// a patch reads intensity as `input|0`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MusicParam {
+    /// How busy the board is. The game's patch speeds its clock up with it.
+    Intensity,
+    /// Opens the low pass filter the game's patch is played through
+    Brightness,
+    /// Brings in the fanfare on the scoring patch
+    Scoring,
+}
+
+pub(crate) const PARAM_COUNT: usize = 3;
+const ALL_PARAMS: [MusicParam; PARAM_COUNT] = [MusicParam::Intensity, MusicParam::Brightness, MusicParam::Scoring];
+
+impl MusicParam {
+    fn default_value(&self) -> f32 {
+        match self {
+            MusicParam::Brightness => 1.0,
+            MusicParam::Intensity | MusicParam::Scoring => 0.0,
+        }
+    }
+
+    /// Every parameter at its default, in input order.
+    pub(crate) fn defaults() -> [f32; PARAM_COUNT] {
+        ALL_PARAMS.map(|param| param.default_value())
+    }
+}
+
+/// Game side handle on the music's parameters. The values are plain atomics
+/// so the audio callback never waits on the game.
+#[derive(Clone)]
+pub struct MusicControl(Arc<[AtomicU32; PARAM_COUNT]>);
+
+impl Default for MusicControl {
+    fn default() -> Self {
+        let control = MusicControl(Arc::new(Default::default()));
+        for param in ALL_PARAMS.iter() {
+            control.set(*param, param.default_value());
+        }
+        control
+    }
+}
+
+impl MusicControl {
+    pub fn set(&self, param: MusicParam, value: f32) {
+        self.0[param as usize].store(value.max(0.0).min(1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self, param: MusicParam) -> f32 {
+        f32::from_bits(self.0[param as usize].load(Ordering::Relaxed))
+    }
+}
+
+/// How quickly parameter changes are followed, per second.
+const SMOOTHING: f32 = 2.0;
+
+/// Audio thread side of the adaptive music. Glides towards the game's
+/// parameters a block at a time so the patches never hear them jump.
+pub struct Adaptive {
+    control: MusicControl,
+    sample_rate: f32,
+    values: [f32; PARAM_COUNT],
+}
+
+impl Adaptive {
+    pub fn new(control: MusicControl, sample_rate: f32) -> Self {
+        Adaptive {
+            values: ALL_PARAMS.map(|param| control.get(param)),
+            control,
+            sample_rate,
+        }
+    }
+
+    /// Follows the game's parameters. Call before running the synth for a
+    /// block of `frames`.
+    pub fn update(&mut self, frames: usize) {
+        let step = (SMOOTHING * frames as f32 / self.sample_rate).min(1.0);
+        for (value, param) in self.values.iter_mut().zip(ALL_PARAMS.iter()) {
+            *value += (self.control.get(*param) - *value) * step;
+        }
+    }
+
+    /// The smoothed parameters, in input order.
+    pub fn values(&self) -> &[f32; PARAM_COUNT] {
+        &self.values
+    }
+}
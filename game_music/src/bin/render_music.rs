@@ -0,0 +1,51 @@
+//! Renders a music patch to a WAV file without touching an audio device.
+//!
+//! `render_music <output.wav> [--patch PATH] [--seconds N] [--sample-rate HZ]`
+use std::{fs::File, io::BufWriter, process};
+
+const USAGE: &str = "usage: render_music <output.wav> [--patch PATH] [--seconds N] [--sample-rate HZ]";
+
+fn main() {
+    let mut output = None;
+    let mut patch = "assets/music/playing.synth".to_string();
+    let mut seconds = 30.0;
+    let mut sample_rate = 44_100;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--patch" => patch = args.next().unwrap_or_else(|| fail("--patch needs a path")),
+            "--seconds" => seconds = parse_value(args.next(), "--seconds"),
+            "--sample-rate" => sample_rate = parse_value(args.next(), "--sample-rate"),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if output.is_none() && !arg.starts_with("--") => output = Some(arg),
+            _ => fail(&format!("unexpected argument {}", arg)),
+        }
+    }
+    let output = output.unwrap_or_else(|| fail("missing output file"));
+
+    let source = std::fs::read_to_string(&patch)
+        .unwrap_or_else(|e| fail(&format!("could not read {}: {}", patch, e)));
+    let samples = game_music::render(&source, seconds, sample_rate)
+        .unwrap_or_else(|e| fail(&format!("could not build {}: {}", patch, e)));
+    let result = File::create(&output)
+        .map_err(hound::Error::from)
+        .and_then(|file| game_music::write_wav(&samples, sample_rate, BufWriter::new(file)));
+    if let Err(e) = result {
+        fail(&format!("could not write {}: {}", output, e));
+    }
+    println!("Rendered {}s of {} at {}Hz to {}", seconds, patch, sample_rate, output);
+}
+
+fn parse_value<T: std::str::FromStr>(value: Option<String>, flag: &str) -> T {
+    value
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| fail(&format!("{} needs a number", flag)))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(1);
+}
@@ -0,0 +1,152 @@
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use bevy::log::warn;
+
+/// A change to the mix, sent from the game to the audio thread.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AudioCommand {
+    MasterVolume(f32),
+    MusicVolume(f32),
+    SfxVolume(f32),
+    Mute(bool),
+    /// Fade out and stop running the synths, or pick up where they left off
+    Pause(bool),
+}
+
+/// Commands that can wait for the audio thread. A power of two so the
+/// indices can wrap.
+const QUEUE_SIZE: usize = 64;
+
+/// Single producer, single consumer ring buffer. The game only writes at
+/// `head` and the audio thread only reads at `tail`, so neither side ever
+/// waits on the other.
+struct CommandQueue {
+    slots: [UnsafeCell<AudioCommand>; QUEUE_SIZE],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// There's one `AudioCommands` and one `CommandReceiver` per queue, neither
+// of them `Clone`, and a slot is only read after `head` has been published
+// past it.
+unsafe impl Sync for CommandQueue {}
+
+/// Game side of the command channel. Commands are dropped when there's no
+/// audio thread to receive them.
+pub struct AudioCommands(Arc<CommandQueue>);
+
+/// Audio thread side of the command channel.
+pub(crate) struct CommandReceiver(Arc<CommandQueue>);
+
+pub(crate) fn channel() -> (AudioCommands, CommandReceiver) {
+    let queue = Arc::new(CommandQueue {
+        slots: [(); QUEUE_SIZE].map(|_| UnsafeCell::new(AudioCommand::Mute(false))),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (AudioCommands(queue.clone()), CommandReceiver(queue))
+}
+
+impl AudioCommands {
+    pub fn send(&mut self, command: AudioCommand) {
+        // The receiver went with the stream, or there never was one
+        if Arc::strong_count(&self.0) < 2 {
+            return;
+        }
+        let head = self.0.head.load(Ordering::Relaxed);
+        let tail = self.0.tail.load(Ordering::Acquire);
+        if head.wrapping_sub(tail) == QUEUE_SIZE {
+            warn!("Audio command queue full, dropped {:?}", command);
+            return;
+        }
+        unsafe { *self.0.slots[head % QUEUE_SIZE].get() = command };
+        self.0.head.store(head.wrapping_add(1), Ordering::Release);
+    }
+}
+
+impl CommandReceiver {
+    fn next(&mut self) -> Option<AudioCommand> {
+        let tail = self.0.tail.load(Ordering::Relaxed);
+        let head = self.0.head.load(Ordering::Acquire);
+        if tail == head {
+            return None;
+        }
+        let command = unsafe { *self.0.slots[tail % QUEUE_SIZE].get() };
+        self.0.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(command)
+    }
+}
+
+/// How long the output takes to follow a change of master volume, mute or
+/// pause, so none of them click.
+const RAMP_SECONDS: f32 = 0.05;
+
+/// Audio thread side of the mix settings: what the game last asked for and
+/// the gain currently ramping towards it.
+pub(crate) struct Mix {
+    commands: CommandReceiver,
+    master_volume: f32,
+    music_volume: f32,
+    sfx_volume: f32,
+    muted: bool,
+    paused: bool,
+    gain: f32,
+    ramp_step: f32,
+}
+
+impl Mix {
+    pub fn new(commands: CommandReceiver, sample_rate: f32) -> Self {
+        Mix {
+            commands,
+            master_volume: 1.0,
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+            muted: false,
+            paused: false,
+            gain: 0.0,
+            ramp_step: 1.0 / (RAMP_SECONDS * sample_rate),
+        }
+    }
+
+    /// Takes in everything the game has sent since the last block.
+    pub fn receive(&mut self) {
+        while let Some(command) = self.commands.next() {
+            match command {
+                AudioCommand::MasterVolume(volume) => self.master_volume = volume.max(0.0).min(1.0),
+                AudioCommand::MusicVolume(volume) => self.music_volume = volume.max(0.0).min(1.0),
+                AudioCommand::SfxVolume(volume) => self.sfx_volume = volume.max(0.0).min(1.0),
+                AudioCommand::Mute(muted) => self.muted = muted,
+                AudioCommand::Pause(paused) => self.paused = paused,
+            }
+        }
+    }
+
+    pub fn music_volume(&self) -> f32 {
+        self.music_volume
+    }
+
+    pub fn sfx_volume(&self) -> f32 {
+        self.sfx_volume
+    }
+
+    /// Paused and faded all the way out, so there's no need to run anything.
+    pub fn is_stopped(&self) -> bool {
+        self.paused && self.gain == 0.0
+    }
+
+    /// Scales a finished block to the master volume.
+    pub fn apply(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let target = if self.muted || self.paused { 0.0 } else { self.master_volume };
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            self.gain += (target - self.gain).max(-self.ramp_step).min(self.ramp_step);
+            *l *= self.gain;
+            *r *= self.gain;
+        }
+    }
+}
@@ -1,46 +1,114 @@
+use std::error::Error;
 use bevy::prelude::*;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use generic_array::typenum::*;
 
-use instruments::{
-    simd_graph::{Node, *},
-    InstrumentSynth,
-};
+mod adaptive;
+mod control;
+mod patch;
+mod player;
+mod render;
+mod sfx;
+pub use adaptive::{MusicControl, MusicParam};
+pub use control::{AudioCommand, AudioCommands};
+pub use patch::{build_patch, SynthPatch, SynthPatchLoader};
+pub use player::MusicTracks;
+pub use render::{render, write_wav};
+pub use sfx::{Sfx, SoundEffects};
+use adaptive::Adaptive;
+use control::{CommandReceiver, Mix};
+use player::{Player, MAX_BLOCK};
+use sfx::{SfxBank, SfxMixer};
+
+/// Size of the blocks the synth is run in when rendering offline.
+const BLOCK_SIZE: usize = 128;
+
+/// Insert before adding `MusicPlugin` to choose whether it opens an audio
+/// device at all.
+#[derive(Copy, Clone, Debug)]
+pub struct AudioOptions {
+    pub enabled: bool,
+}
 
-include!(concat!(env!("OUT_DIR"), "/synth.rs"));
+impl Default for AudioOptions {
+    fn default() -> Self {
+        AudioOptions { enabled: true }
+    }
+}
+
+/// The running output stream, or `None` when audio is disabled or there is
+/// no device to play on and the game runs silently.
+pub struct SynthStream(pub Option<cpal::Stream>);
 
-pub struct SynthStream(cpal::Stream);
 pub struct MusicPlugin;
 impl Plugin for MusicPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        let builder = InstrumentSynth::builder();
-
-        let dsp_graph = build_synth();
-
-        let mut synth = builder.build_with_synth(dsp_graph);
-
-        let cpal_host = cpal::default_host();
-
-        let device = cpal_host.default_output_device().unwrap();
-        let config = device.default_output_config().unwrap();
-
-        let sample_rate = config.sample_rate().0 as f32;
-        synth.set_sample_rate(sample_rate);
-        let cpal_stream = match config.sample_format() {
-            cpal::SampleFormat::F32 => run::<f32>(&device, &config.into(), synth),
-            cpal::SampleFormat::I16 => run::<i16>(&device, &config.into(), synth),
-            cpal::SampleFormat::U16 => run::<u16>(&device, &config.into(), synth),
+        let options = app.world().get_resource::<AudioOptions>().copied().unwrap_or_default();
+        let control = MusicControl::default();
+        let effects = SoundEffects::default();
+        let tracks = MusicTracks::default();
+        let (commands, receiver) = control::channel();
+        let stream = if options.enabled {
+            match start_stream(control.clone(), effects.clone(), tracks.clone(), receiver) {
+                Ok(stream) => Some(stream),
+                Err(e) => {
+                    warn!("Could not start audio, continuing without sound: {}", e);
+                    None
+                }
+            }
+        } else {
+            info!("Audio disabled");
+            None
         };
 
-        app.insert_non_send_resource(SynthStream(cpal_stream));
+        app.insert_non_send_resource(SynthStream(stream));
+        app.insert_resource(control);
+        app.insert_resource(effects);
+        app.insert_resource(tracks);
+        app.insert_resource(commands);
+        app.add_asset::<SynthPatch>();
+        app.init_asset_loader::<SynthPatchLoader>();
     }
 }
 
+fn start_stream(
+    control: MusicControl,
+    effects: SoundEffects,
+    tracks: MusicTracks,
+    commands: CommandReceiver,
+) -> Result<cpal::Stream, Box<dyn Error>> {
+    let cpal_host = cpal::default_host();
+
+    let device = cpal_host.default_output_device().ok_or("no output device")?;
+    let config = device.default_output_config()?;
+
+    let sample_rate = config.sample_rate().0 as f32;
+    // Silent until the game picks a track
+    let player = Player::new(tracks, sample_rate);
+    let adaptive = Adaptive::new(control, sample_rate);
+    let mixer = SfxMixer::new(effects, SfxBank::render(sample_rate));
+    let mix = Mix::new(commands, sample_rate);
+    let audio = AudioThread { player, adaptive, mixer, mix };
+    match config.sample_format() {
+        cpal::SampleFormat::F32 => run::<f32>(&device, &config.into(), audio),
+        cpal::SampleFormat::I16 => run::<i16>(&device, &config.into(), audio),
+        cpal::SampleFormat::U16 => run::<u16>(&device, &config.into(), audio),
+    }
+}
+
+/// Everything the output callback owns. Built before the stream starts so
+/// the callback itself never allocates.
+struct AudioThread {
+    player: Player,
+    adaptive: Adaptive,
+    mixer: SfxMixer,
+    mix: Mix,
+}
+
 fn run<T>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
-    mut synth: InstrumentSynth,
-) -> cpal::Stream
+    mut audio: AudioThread,
+) -> Result<cpal::Stream, Box<dyn Error>>
 where
     T: cpal::Sample,
 {
@@ -48,42 +116,52 @@ where
 
     let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
 
-    let mut outputs = vec![vec![0.0; 128]; 2];
+    let mut outputs = vec![Vec::with_capacity(MAX_BLOCK), Vec::with_capacity(MAX_BLOCK)];
 
     let stream = device
         .build_output_stream(
             config,
             move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-                write_data(data, channels, &mut synth, &mut outputs)
+                write_data(data, channels, &mut audio, &mut outputs)
             },
             err_fn,
-        )
-        .unwrap();
-    stream.play().unwrap();
-    stream
+        )?;
+    stream.play()?;
+    Ok(stream)
 }
 
 fn write_data<T>(
     output: &mut [T],
     channels: usize,
-    synth: &mut InstrumentSynth,
+    audio: &mut AudioThread,
     outputs: &mut Vec<Vec<f32>>,
 ) where
     T: cpal::Sample,
 {
-    outputs[0].resize(output.len() / 2, 0.0);
-    outputs[0].fill(0.0);
-    outputs[1].resize(output.len() / 2, 0.0);
-    outputs[1].fill(0.0);
-
-    let (left, tail) = outputs.split_at_mut(1);
-    synth.process(&mut left[0], &mut tail[0]);
-
-    for (i, frame) in output.chunks_mut(channels).enumerate() {
-        let value_left = outputs[0][i];
-        let value_right = outputs[1][i];
-
-        frame[0] = cpal::Sample::from::<f32>(&(value_left * 0.5));
-        frame[1] = cpal::Sample::from::<f32>(&(value_right * 0.5));
+    audio.mix.receive();
+    // In blocks the buffers already have room for, so nothing allocates
+    for block in output.chunks_mut(MAX_BLOCK * channels) {
+        if audio.mix.is_stopped() {
+            for sample in block.iter_mut() {
+                *sample = cpal::Sample::from::<f32>(&0.0);
+            }
+            audio.mixer.discard_requested();
+            continue;
+        }
+
+        let frames = block.len() / channels;
+        outputs[0].resize(frames, 0.0);
+        outputs[1].resize(frames, 0.0);
+
+        audio.adaptive.update(frames);
+        let (left, tail) = outputs.split_at_mut(1);
+        audio.player.process(&mut left[0], &mut tail[0], audio.adaptive.values());
+        audio.mixer.mix(&mut left[0], &mut tail[0], audio.mix.music_volume(), audio.mix.sfx_volume());
+        audio.mix.apply(&mut left[0], &mut tail[0]);
+
+        for (i, frame) in block.chunks_mut(channels).enumerate() {
+            frame[0] = cpal::Sample::from::<f32>(&outputs[0][i]);
+            frame[1] = cpal::Sample::from::<f32>(&outputs[1][i]);
+        }
     }
 }
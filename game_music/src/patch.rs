@@ -0,0 +1,55 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use instruments::{dynamic_graph::DynamicGraphBuilder, InstrumentSynth};
+
+use crate::adaptive::PARAM_COUNT;
+
+/// A `.synth` music patch. Kept as source and built into a synth when it's
+/// played, so it can be edited and reloaded while the game runs.
+#[derive(Debug, TypeUuid)]
+#[uuid = "5b8f3c1e-2a47-4d0e-9c6b-7e1f0a3d9b52"]
+pub struct SynthPatch {
+    pub source: String,
+}
+
+#[derive(Default)]
+pub struct SynthPatchLoader;
+
+impl AssetLoader for SynthPatchLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let source = std::str::from_utf8(bytes)?.to_string();
+            load_context.set_default_asset(LoadedAsset::new(SynthPatch { source }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["synth"]
+    }
+}
+
+/// Parses a patch and builds a synth from it running at `sample_rate`.
+pub fn build_patch(source: &str, sample_rate: f32) -> Result<InstrumentSynth, String> {
+    let graph = DynamicGraphBuilder::default()
+        .parse(source)
+        .map_err(|e| format!("{:?}", e))?;
+    let mut synth = InstrumentSynth::builder().build_with_synth(graph);
+    synth.set_sample_rate(sample_rate);
+    Ok(synth)
+}
+
+/// Feeds the game's parameters to the patch's `input|n` nodes. Only stores
+/// values, so it's safe to call from the audio thread.
+pub(crate) fn set_inputs(synth: &mut InstrumentSynth, values: &[f32; PARAM_COUNT]) {
+    for (index, value) in values.iter().enumerate() {
+        synth.set_input(index, *value);
+    }
+}
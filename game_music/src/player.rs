@@ -0,0 +1,189 @@
+use std::{
+    f32::consts::FRAC_PI_2,
+    ptr,
+    sync::{
+        atomic::{AtomicPtr, AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use bevy::log::info;
+use instruments::InstrumentSynth;
+
+use crate::{
+    adaptive::PARAM_COUNT,
+    patch::{build_patch, set_inputs},
+};
+
+/// How long one track takes to fade into the next.
+const CROSSFADE_SECONDS: f32 = 2.0;
+
+/// Largest block the audio thread has buffers ready for.
+pub const MAX_BLOCK: usize = 8192;
+
+pub struct Track {
+    name: String,
+    synth: InstrumentSynth,
+}
+
+impl Track {
+    fn process(&mut self, left: &mut Vec<f32>, right: &mut Vec<f32>, inputs: &[f32; PARAM_COUNT]) {
+        set_inputs(&mut self.synth, inputs);
+        self.synth.process(left, right);
+    }
+}
+
+/// Hands built tracks to the audio thread and takes finished ones back, one
+/// at a time in each direction. Both slots are swapped atomically, so the
+/// audio thread never waits, allocates or frees.
+struct Mailbox {
+    /// Sample rate of the output stream, zero until one is running
+    sample_rate: AtomicU32,
+    incoming: AtomicPtr<Track>,
+    retired: AtomicPtr<Track>,
+}
+
+impl Drop for Mailbox {
+    fn drop(&mut self) {
+        for slot in [&self.incoming, &self.retired].iter() {
+            let track = slot.swap(ptr::null_mut(), Ordering::AcqRel);
+            if !track.is_null() {
+                drop(unsafe { Box::from_raw(track) });
+            }
+        }
+    }
+}
+
+/// Game side handle for changing what music is playing.
+#[derive(Clone)]
+pub struct MusicTracks(Arc<Mailbox>);
+
+impl Default for MusicTracks {
+    fn default() -> Self {
+        MusicTracks(Arc::new(Mailbox {
+            sample_rate: AtomicU32::new(0),
+            incoming: AtomicPtr::new(ptr::null_mut()),
+            retired: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+impl MusicTracks {
+    /// Builds a track from `.synth` source and crossfades to it. Does nothing
+    /// when there's no audio output.
+    pub fn play(&self, name: &str, source: &str) -> Result<(), String> {
+        let sample_rate = self.0.sample_rate.load(Ordering::Acquire);
+        if sample_rate == 0 {
+            return Ok(());
+        }
+        let track = Box::new(Track {
+            name: name.to_string(),
+            synth: build_patch(source, sample_rate as f32)?,
+        });
+        // A track the audio thread hasn't picked up yet is replaced outright
+        let stale = self.0.incoming.swap(Box::into_raw(track), Ordering::AcqRel);
+        if !stale.is_null() {
+            drop(unsafe { Box::from_raw(stale) });
+        }
+        Ok(())
+    }
+
+    /// Frees a track the audio thread has finished fading out.
+    pub fn collect_retired(&self) {
+        let track = self.0.retired.swap(ptr::null_mut(), Ordering::AcqRel);
+        if !track.is_null() {
+            let track = unsafe { Box::from_raw(track) };
+            info!("Finished playing {}", track.name);
+        }
+    }
+}
+
+/// Audio thread side: the playing track and the one fading out.
+pub struct Player {
+    tracks: MusicTracks,
+    sample_rate: f32,
+    current: Option<Box<Track>>,
+    fading: Option<Box<Track>>,
+    /// Progress of the crossfade, 1 once it's done
+    fade: f32,
+    scratch: [Vec<f32>; 2],
+}
+
+impl Player {
+    pub fn new(tracks: MusicTracks, sample_rate: f32) -> Self {
+        tracks.0.sample_rate.store(sample_rate as u32, Ordering::Release);
+        Player {
+            tracks,
+            sample_rate,
+            current: None,
+            fading: None,
+            fade: 1.0,
+            scratch: [Vec::with_capacity(MAX_BLOCK), Vec::with_capacity(MAX_BLOCK)],
+        }
+    }
+
+    /// Passes a faded out track back to the game, and picks up a new one if
+    /// there is one and nothing is still fading.
+    fn exchange(&mut self) {
+        if self.fade >= 1.0 {
+            if let Some(track) = self.fading.take() {
+                let track = Box::into_raw(track);
+                let swapped = self.tracks.0.retired.compare_exchange(
+                    ptr::null_mut(),
+                    track,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                );
+                if swapped.is_err() {
+                    // The game hasn't collected the last one yet
+                    self.fading = Some(unsafe { Box::from_raw(track) });
+                    return;
+                }
+            }
+        }
+        if self.fading.is_some() {
+            return;
+        }
+        let incoming = self.tracks.0.incoming.swap(ptr::null_mut(), Ordering::AcqRel);
+        if !incoming.is_null() {
+            self.fading = self.current.take();
+            self.current = Some(unsafe { Box::from_raw(incoming) });
+            self.fade = 0.0;
+        }
+    }
+
+    /// Renders a block of music into `left` and `right`, which must be no
+    /// longer than `MAX_BLOCK`, with the patches' inputs set to `inputs`.
+    pub fn process(&mut self, left: &mut Vec<f32>, right: &mut Vec<f32>, inputs: &[f32; PARAM_COUNT]) {
+        self.exchange();
+        left.fill(0.0);
+        right.fill(0.0);
+        let fade_start = self.fade;
+        self.fade = (self.fade + left.len() as f32 / (CROSSFADE_SECONDS * self.sample_rate)).min(1.0);
+        let fade_step = (self.fade - fade_start) / left.len().max(1) as f32;
+
+        if let Some(current) = self.current.as_mut() {
+            current.process(left, right, inputs);
+            for (i, (l, r)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+                let gain = ((fade_start + fade_step * i as f32) * FRAC_PI_2).sin();
+                *l *= gain;
+                *r *= gain;
+            }
+        }
+        if let Some(fading) = self.fading.as_mut() {
+            if fade_start < 1.0 {
+                let [scratch_left, scratch_right] = &mut self.scratch;
+                scratch_left.clear();
+                scratch_left.resize(left.len(), 0.0);
+                scratch_right.clear();
+                scratch_right.resize(right.len(), 0.0);
+                fading.process(scratch_left, scratch_right, inputs);
+                for (i, (l, r)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+                    let gain = ((fade_start + fade_step * i as f32) * FRAC_PI_2).cos();
+                    *l += scratch_left[i] * gain;
+                    *r += scratch_right[i] * gain;
+                }
+            }
+        }
+    }
+}
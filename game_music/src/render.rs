@@ -0,0 +1,55 @@
+//! Rendering the music without an audio device, for listening to changes to
+//! the `.synth` patches offline and for comparing output between versions.
+use std::io::{Seek, Write};
+
+use instruments::InstrumentSynth;
+
+use crate::{adaptive::MusicParam, build_patch, patch::set_inputs, BLOCK_SIZE};
+
+/// Renders `seconds` of the patch in `source` at `sample_rate`, as
+/// interleaved left/right samples at full volume, with every `MusicParam`
+/// at its default. The synth is deterministic, so the same patch always
+/// renders the same samples.
+pub fn render(source: &str, seconds: f32, sample_rate: u32) -> Result<Vec<f32>, String> {
+    let mut synth = build_patch(source, sample_rate as f32)?;
+    set_inputs(&mut synth, &MusicParam::defaults());
+    let frames = (seconds * sample_rate as f32).round() as usize;
+    Ok(render_synth(&mut synth, frames))
+}
+
+/// Runs `synth` for `frames` and returns its output interleaved.
+pub(crate) fn render_synth(synth: &mut InstrumentSynth, frames: usize) -> Vec<f32> {
+    let mut left = vec![0.0; BLOCK_SIZE];
+    let mut right = vec![0.0; BLOCK_SIZE];
+    let mut samples = Vec::with_capacity(frames * 2);
+    let mut remaining = frames;
+    while remaining > 0 {
+        let block = remaining.min(BLOCK_SIZE);
+        left.resize(block, 0.0);
+        left.fill(0.0);
+        right.resize(block, 0.0);
+        right.fill(0.0);
+        synth.process(&mut left, &mut right);
+        for (l, r) in left.iter().zip(right.iter()) {
+            samples.push(*l);
+            samples.push(*r);
+        }
+        remaining -= block;
+    }
+    samples
+}
+
+/// Writes interleaved stereo samples from `render` as a 32 bit float WAV.
+pub fn write_wav<W: Write + Seek>(samples: &[f32], sample_rate: u32, writer: W) -> Result<(), hound::Error> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut wav = hound::WavWriter::new(writer, spec)?;
+    for sample in samples {
+        wav.write_sample(*sample)?;
+    }
+    wav.finalize()
+}
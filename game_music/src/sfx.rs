@@ -0,0 +1,176 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use bevy::log::warn;
+
+use crate::{build_patch, render::render_synth};
+
+/// The game's sound effects. Each is a small patch for the same synth as
+/// the music.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sfx {
+    Place,
+    Munch,
+    FenceSmash,
+    Gust,
+    Harvest,
+    Fanfare,
+    Invalid,
+}
+
+const SFX_COUNT: usize = 7;
+const ALL_SFX: [Sfx; SFX_COUNT] = [
+    Sfx::Place,
+    Sfx::Munch,
+    Sfx::FenceSmash,
+    Sfx::Gust,
+    Sfx::Harvest,
+    Sfx::Fanfare,
+    Sfx::Invalid,
+];
+
+impl Sfx {
+    fn seconds(&self) -> f32 {
+        match self {
+            Sfx::Place => 0.15,
+            Sfx::Munch => 0.3,
+            Sfx::FenceSmash => 0.45,
+            Sfx::Gust => 0.9,
+            Sfx::Harvest => 0.35,
+            Sfx::Fanfare => 0.8,
+            Sfx::Invalid => 0.2,
+        }
+    }
+
+    /// The effect's patch. Built into the crate, the effects have to be
+    /// ready before the stream starts.
+    fn source(&self) -> &'static str {
+        match self {
+            Sfx::Place => include_str!("../../assets/sfx/place.synth"),
+            Sfx::Munch => include_str!("../../assets/sfx/munch.synth"),
+            Sfx::FenceSmash => include_str!("../../assets/sfx/fence_smash.synth"),
+            Sfx::Gust => include_str!("../../assets/sfx/gust.synth"),
+            Sfx::Harvest => include_str!("../../assets/sfx/harvest.synth"),
+            Sfx::Fanfare => include_str!("../../assets/sfx/fanfare.synth"),
+            Sfx::Invalid => include_str!("../../assets/sfx/invalid.synth"),
+        }
+    }
+}
+
+/// Fade at the end of every effect, so one cut off mid note doesn't click.
+const TAIL_SECONDS: f32 = 0.01;
+
+/// Every effect rendered through its patch at the stream's sample rate, as
+/// interleaved left/right samples.
+pub struct SfxBank([Vec<f32>; SFX_COUNT]);
+
+impl SfxBank {
+    pub fn render(sample_rate: f32) -> Self {
+        SfxBank(ALL_SFX.map(|sfx| {
+            let frames = (sfx.seconds() * sample_rate).round() as usize;
+            let mut samples = match build_patch(sfx.source(), sample_rate) {
+                Ok(mut synth) => render_synth(&mut synth, frames),
+                Err(e) => {
+                    warn!("Could not build the {:?} effect: {}", sfx, e);
+                    Vec::new()
+                }
+            };
+            let tail = ((TAIL_SECONDS * sample_rate) as usize).min(samples.len() / 2);
+            for i in 0..tail {
+                let gain = i as f32 / tail as f32;
+                let frame = samples.len() / 2 - 1 - i;
+                samples[frame * 2] *= gain;
+                samples[frame * 2 + 1] *= gain;
+            }
+            samples
+        }))
+    }
+}
+
+/// Game side handle for playing effects. Requests are counted in atomics so
+/// nothing the audio thread does can block.
+#[derive(Clone, Default)]
+pub struct SoundEffects(Arc<[AtomicU32; SFX_COUNT]>);
+
+impl SoundEffects {
+    pub fn play(&self, sfx: Sfx) {
+        self.0[sfx as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Effects that can be heard at once. Requests beyond this are dropped.
+const MAX_VOICES: usize = 16;
+
+#[derive(Copy, Clone)]
+struct Voice {
+    sfx: Sfx,
+    /// Next frame of the effect to play
+    frame: usize,
+}
+
+/// Audio thread side of the effects: the voices that are playing and the
+/// final mix with the music.
+pub struct SfxMixer {
+    effects: SoundEffects,
+    bank: SfxBank,
+    voices: [Option<Voice>; MAX_VOICES],
+}
+
+impl SfxMixer {
+    pub fn new(effects: SoundEffects, bank: SfxBank) -> Self {
+        SfxMixer {
+            effects,
+            bank,
+            voices: [None; MAX_VOICES],
+        }
+    }
+
+    fn start_requested(&mut self) {
+        for sfx in ALL_SFX.iter() {
+            let requested = self.effects.0[*sfx as usize].swap(0, Ordering::Relaxed);
+            for _ in 0..requested {
+                match self.voices.iter_mut().find(|v| v.is_none()) {
+                    Some(slot) => *slot = Some(Voice { sfx: *sfx, frame: 0 }),
+                    None => break,
+                }
+            }
+        }
+    }
+
+    /// Drops effects asked for while the output is stopped, rather than
+    /// playing them all at once when it starts again.
+    pub fn discard_requested(&mut self) {
+        for request in self.effects.0.iter() {
+            request.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Scales a block of music to `music_volume` and mixes the effects in at
+    /// `sfx_volume`.
+    pub fn mix(&mut self, left: &mut [f32], right: &mut [f32], music_volume: f32, sfx_volume: f32) {
+        self.start_requested();
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            *l *= music_volume;
+            *r *= music_volume;
+        }
+        for slot in self.voices.iter_mut() {
+            if let Some(voice) = slot {
+                let samples = &self.bank.0[voice.sfx as usize];
+                let frames = samples.len() / 2;
+                for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                    if voice.frame >= frames {
+                        break;
+                    }
+                    *l += samples[voice.frame * 2] * sfx_volume;
+                    *r += samples[voice.frame * 2 + 1] * sfx_volume;
+                    voice.frame += 1;
+                }
+                if voice.frame >= frames {
+                    *slot = None;
+                }
+            }
+        }
+    }
+}
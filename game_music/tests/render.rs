@@ -0,0 +1,57 @@
+use std::{fs, io::Cursor, path::PathBuf};
+
+/// Short and at a low rate, so the reference stays small.
+const SNAPSHOT_SECONDS: f32 = 0.5;
+const SNAPSHOT_RATE: u32 = 8_000;
+/// Allowed difference per sample, for floating point differences between
+/// platforms.
+const TOLERANCE: f32 = 1e-4;
+
+fn manifest_path(path: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+fn read_wav(bytes: &[u8]) -> (hound::WavSpec, Vec<f32>) {
+    let mut reader = hound::WavReader::new(Cursor::new(bytes)).unwrap();
+    let samples = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+    (reader.spec(), samples)
+}
+
+#[test]
+fn write_wav_reads_back() {
+    let samples: Vec<f32> = (0..200).map(|i| (i as f32 * 0.1).sin() * 0.5).collect();
+    let mut bytes = Cursor::new(Vec::new());
+    game_music::write_wav(&samples, 22_050, &mut bytes).unwrap();
+
+    let (spec, read) = read_wav(bytes.get_ref());
+    assert_eq!(spec.channels, 2);
+    assert_eq!(spec.sample_rate, 22_050);
+    assert_eq!(spec.sample_format, hound::SampleFormat::Float);
+    assert_eq!(read, samples);
+}
+
+/// Compares the start of the game's music with `tests/snapshots/playing.wav`.
+/// Run with `UPDATE_SNAPSHOTS=1` to write a new reference after a deliberate
+/// change to the patch or the synth.
+#[test]
+fn playing_matches_snapshot() {
+    let source = fs::read_to_string(manifest_path("../assets/music/playing.synth")).unwrap();
+    let samples = game_music::render(&source, SNAPSHOT_SECONDS, SNAPSHOT_RATE).unwrap();
+    assert_eq!(samples.len(), (SNAPSHOT_SECONDS * SNAPSHOT_RATE as f32) as usize * 2);
+
+    let snapshot = manifest_path("tests/snapshots/playing.wav");
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(snapshot.parent().unwrap()).unwrap();
+        let file = fs::File::create(&snapshot).unwrap();
+        game_music::write_wav(&samples, SNAPSHOT_RATE, std::io::BufWriter::new(file)).unwrap();
+        return;
+    }
+    let bytes = fs::read(&snapshot)
+        .unwrap_or_else(|e| panic!("no snapshot at {} ({}), run with UPDATE_SNAPSHOTS=1", snapshot.display(), e));
+    let (spec, expected) = read_wav(&bytes);
+    assert_eq!(spec.sample_rate, SNAPSHOT_RATE);
+    assert_eq!(expected.len(), samples.len());
+    for (i, (got, want)) in samples.iter().zip(expected.iter()).enumerate() {
+        assert!((got - want).abs() <= TOLERANCE, "sample {} is {}, snapshot has {}", i, got, want);
+    }
+}
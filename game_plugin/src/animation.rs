@@ -0,0 +1,152 @@
+use std::f32::consts::PI;
+use bevy::{prelude::*, transform::TransformSystem};
+use crate::{
+    map::{TilePos, TILE_SIZE, tile_to_world},
+    settings::Settings,
+    turn_structure::TurnSpeed,
+};
+
+/// How long a move from one tile to the next takes on screen at most. This
+/// runs on real time, independent of the `TurnClock` steps that moves things
+/// logically, but is shortened to keep up at higher game speeds.
+const TWEEN_SECONDS: f32 = 0.15;
+
+fn animations_off(settings: &Settings, speed: &TurnSpeed) -> bool {
+    settings.skip_animations || speed.0.tick_seconds().is_none()
+}
+
+fn tween_seconds(speed: &TurnSpeed) -> f32 {
+    speed.0.tick_seconds().map_or(0.0, |tick| TWEEN_SECONDS.min(tick * 1.5))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TweenStyle {
+    /// Jumps in an arc, squashing on landing
+    Hop,
+    /// Rushes forward and stretches out along the way
+    Gust,
+}
+
+/// Marks entities whose moves between tiles are animated rather than snapped
+/// by `update_tile_position`.
+pub struct Animated(pub TweenStyle);
+
+/// The tile an animated entity was last sent towards.
+struct ShownTile(IVec2);
+
+/// A move in progress. While any of these exist the pests' turn waits.
+pub struct Tween {
+    from: Vec2,
+    to: Vec2,
+    elapsed: f32,
+    style: TweenStyle,
+}
+
+impl Tween {
+    /// Puts the entity where it should be `t` (0 to 1) of the way through.
+    fn apply(&self, t: f32, transform: &mut Transform) {
+        let eased = match self.style {
+            TweenStyle::Hop => ease_in_out(t),
+            TweenStyle::Gust => ease_out(t),
+        };
+        let position = self.from.lerp(self.to, eased);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        match self.style {
+            TweenStyle::Hop => {
+                transform.translation.y += (PI * t).sin() * TILE_SIZE as f32 * 0.3;
+                let squash = if t > 0.8 { 1.0 - 0.15 * (PI * (t - 0.8) / 0.2).sin() } else { 1.0 };
+                transform.scale = Vec3::new(1.0 / squash, squash, 1.0);
+            }
+            TweenStyle::Gust => {
+                let stretch = 1.0 + 0.4 * (PI * t).sin();
+                transform.scale = Vec3::new(stretch, 1.0 / stretch, 1.0);
+            }
+        }
+    }
+}
+
+fn ease_in_out(t: f32) -> f32 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+    }
+}
+
+fn ease_out(t: f32) -> f32 {
+    1.0 - (1.0 - t).powi(3)
+}
+
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            start_tweens.system().label("start tweens").before(TransformSystem::TransformPropagate)
+        );
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            run_tweens.system().after("start tweens").before(TransformSystem::TransformPropagate)
+        );
+    }
+}
+
+fn start_tweens(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    speed: Res<TurnSpeed>,
+    mut query: Query<(Entity, &TilePos, &Animated, &mut Transform, Option<&ShownTile>)>,
+) {
+    for (e, pos, animated, mut transform, shown) in query.iter_mut() {
+        let target = tile_to_world(pos.0);
+        match shown {
+            Some(shown) if shown.0 == pos.0 => continue,
+            // Freshly spawned, or animations are off: just put it there
+            None => snap(&mut transform, target),
+            Some(_) if animations_off(&settings, &speed) => snap(&mut transform, target),
+            Some(_) => {
+                // A move that starts before the last one finished carries on
+                // from wherever the entity is now.
+                commands.entity(e).insert(Tween {
+                    from: transform.translation.truncate(),
+                    to: target,
+                    elapsed: 0.0,
+                    style: animated.0,
+                });
+            }
+        }
+        commands.entity(e).insert(ShownTile(pos.0));
+    }
+}
+
+fn run_tweens(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    speed: Res<TurnSpeed>,
+    mut query: Query<(Entity, &mut Tween, &mut Transform)>,
+) {
+    let duration = tween_seconds(&speed);
+    for (e, mut tween, mut transform) in query.iter_mut() {
+        tween.elapsed += time.delta_seconds();
+        let t = if animations_off(&settings, &speed) {
+            1.0
+        } else {
+            (tween.elapsed / duration).min(1.0)
+        };
+        if t >= 1.0 {
+            snap(&mut transform, tween.to);
+            commands.entity(e).remove::<Tween>();
+        } else {
+            tween.apply(t, &mut transform);
+        }
+    }
+}
+
+fn snap(transform: &mut Transform, position: Vec2) {
+    transform.translation.x = position.x;
+    transform.translation.y = position.y;
+    transform.scale = Vec3::ONE;
+}
@@ -0,0 +1,238 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+use crate::{
+    GameState,
+    events::GameEvent,
+    loading::FontAssets,
+    map::{TILE_SIZE, tile_to_world},
+};
+
+/// Effects are drawn above the board but below the overlay.
+const EFFECT_Z: f32 = 60.0;
+
+pub struct EffectsPlugin;
+
+impl Plugin for EffectsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(spawn_bite_particles.system())
+                .with_system(flash_damaged_tiles.system())
+                .with_system(spawn_fence_debris.system())
+                .with_system(spawn_harvest_pops.system())
+                .with_system(update_particles.system())
+                .with_system(update_floating_text.system())
+                .with_system(update_flashes.system())
+        );
+        app.add_system_set(
+            SystemSet::on_exit(GameState::Playing)
+                .with_system(despawn_effects.system())
+        );
+    }
+}
+
+/// A short lived bit of debris that flies off and fades out.
+struct Particle {
+    velocity: Vec2,
+    spin: f32,
+    lifetime: Timer,
+}
+
+/// A number that drifts upwards and fades out.
+struct FloatingText {
+    lifetime: Timer,
+}
+
+/// Tints a tile's sprite red for a moment.
+struct Flash(Timer);
+
+fn spawn_particles(
+    commands: &mut Commands,
+    materials: &mut Assets<ColorMaterial>,
+    pos: IVec2,
+    color: Color,
+    count: usize,
+    size: f32,
+    speed: f32,
+) {
+    // Purely cosmetic, so this stays away from `GameRng` and leaves the run
+    // unchanged.
+    let mut rng = thread_rng();
+    let center = tile_to_world(pos);
+    for _ in 0..count {
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let velocity = Vec2::new(angle.cos(), angle.sin()) * speed * rng.gen_range(0.5..1.0);
+        commands.spawn_bundle(SpriteBundle {
+            material: materials.add(color.into()),
+            sprite: Sprite::new(Vec2::splat(size)),
+            transform: Transform::from_translation(center.extend(EFFECT_Z)),
+            ..Default::default()
+        }).insert(Particle {
+            velocity,
+            spin: rng.gen_range(-10.0..10.0),
+            lifetime: Timer::from_seconds(rng.gen_range(0.3..0.6), false),
+        });
+    }
+}
+
+fn spawn_floating_text(
+    commands: &mut Commands,
+    fonts: &FontAssets,
+    pos: IVec2,
+    value: String,
+    color: Color,
+) {
+    let start = tile_to_world(pos) + Vec2::new(0.0, TILE_SIZE as f32 / 2.0);
+    commands.spawn_bundle(Text2dBundle {
+        text: Text::with_section(
+            value,
+            TextStyle {
+                font: fonts.fira_sans.clone(),
+                font_size: 32.0,
+                color,
+            },
+            TextAlignment {
+                vertical: VerticalAlign::Center,
+                horizontal: HorizontalAlign::Center,
+            },
+        ),
+        transform: Transform::from_translation(start.extend(EFFECT_Z)),
+        ..Default::default()
+    }).insert(FloatingText {
+        lifetime: Timer::from_seconds(0.8, false),
+    });
+}
+
+fn spawn_bite_particles(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut events: EventReader<GameEvent>,
+) {
+    for event in events.iter() {
+        match event {
+            GameEvent::PlantDamaged { pos, .. } | GameEvent::PlantEaten { pos } => {
+                spawn_particles(&mut commands, &mut materials, *pos, Color::rgb(0.4, 0.8, 0.2), 8, 6.0, 120.0);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn flash_damaged_tiles(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    mut events: EventReader<GameEvent>,
+    exists: Query<Entity>,
+) {
+    for event in events.iter() {
+        if let GameEvent::PlantDamaged { entity, pos } = event {
+            // The tile may have been judged or cleaned up in the meantime
+            if exists.get(*entity).is_ok() {
+                commands.entity(*entity).insert(Flash(Timer::from_seconds(0.3, false)));
+            }
+            spawn_floating_text(&mut commands, &fonts, *pos, "-1".to_string(), Color::rgb(0.9, 0.1, 0.1));
+        }
+    }
+}
+
+fn spawn_fence_debris(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut events: EventReader<GameEvent>,
+) {
+    for event in events.iter() {
+        if let GameEvent::FenceDestroyed { pos } = event {
+            spawn_particles(&mut commands, &mut materials, *pos, Color::rgb(0.55, 0.35, 0.15), 12, 10.0, 200.0);
+        }
+    }
+}
+
+fn spawn_harvest_pops(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    fonts: Res<FontAssets>,
+    mut events: EventReader<GameEvent>,
+) {
+    for event in events.iter() {
+        if let GameEvent::PlantMatured { pos, value } = event {
+            spawn_particles(&mut commands, &mut materials, *pos, Color::rgb(1.0, 0.85, 0.2), 10, 6.0, 150.0);
+            spawn_floating_text(&mut commands, &fonts, *pos, format!("+{}", value), Color::rgb(1.0, 0.85, 0.2));
+        }
+    }
+}
+
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(Entity, &mut Particle, &mut Transform, &Handle<ColorMaterial>)>,
+) {
+    for (e, mut particle, mut transform, material) in query.iter_mut() {
+        particle.lifetime.tick(time.delta());
+        if particle.lifetime.finished() {
+            commands.entity(e).despawn_recursive();
+            continue
+        }
+        let delta = particle.velocity * time.delta_seconds();
+        transform.translation += delta.extend(0.0);
+        transform.rotate(Quat::from_rotation_z(particle.spin * time.delta_seconds()));
+        // A little gravity
+        particle.velocity.y -= 400.0 * time.delta_seconds();
+        if let Some(material) = materials.get_mut(material) {
+            material.color.set_a(1.0 - particle.lifetime.percent());
+        }
+    }
+}
+
+fn update_floating_text(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut FloatingText, &mut Transform, &mut Text)>,
+) {
+    for (e, mut floating, mut transform, mut text) in query.iter_mut() {
+        floating.lifetime.tick(time.delta());
+        if floating.lifetime.finished() {
+            commands.entity(e).despawn_recursive();
+            continue
+        }
+        transform.translation.y += 40.0 * time.delta_seconds();
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(1.0 - floating.lifetime.percent());
+        }
+    }
+}
+
+fn update_flashes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(Entity, &mut Flash, Option<&Handle<ColorMaterial>>, Option<&mut TextureAtlasSprite>)>,
+) {
+    for (e, mut flash, material, sprite) in query.iter_mut() {
+        flash.0.tick(time.delta());
+        // Blink twice over the course of the flash
+        let color = if flash.0.finished() || flash.0.percent() % 0.5 > 0.25 {
+            Color::WHITE
+        } else {
+            Color::rgb(1.0, 0.3, 0.3)
+        };
+        if let Some(material) = material.and_then(|m| materials.get_mut(m)) {
+            material.color = color;
+        }
+        if let Some(mut sprite) = sprite {
+            sprite.color = color;
+        }
+        if flash.0.finished() {
+            commands.entity(e).remove::<Flash>();
+        }
+    }
+}
+
+fn despawn_effects(
+    mut commands: Commands,
+    query: Query<Entity, Or<(With<Particle>, With<FloatingText>)>>,
+) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
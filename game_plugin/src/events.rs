@@ -0,0 +1,49 @@
+use bevy::prelude::*;
+use crate::{
+    main_ui::PlacableTile,
+    scoring::PrizeTier,
+    turn_structure::RoundEndReason,
+};
+
+/// Everything of note that happens during a run, as one stream. Core
+/// systems send these; feedback, statistics and logging read them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    /// The player put a tile on the board, with its top left at `pos`
+    TilePlaced { tile: PlacableTile, pos: IVec2, rotation: u8 },
+    /// The player tried to put a tile where it doesn't fit
+    PlacementRejected { pos: IVec2 },
+    /// A pest bit a plant that survived the bite
+    PlantDamaged { entity: Entity, pos: IVec2 },
+    /// A pest finished a plant off
+    PlantEaten { pos: IVec2 },
+    /// The wind blew a fence away
+    FenceDestroyed { pos: IVec2 },
+    /// A plant reached maturity and was harvested for `value`
+    PlantMatured { pos: IVec2, value: u32 },
+    /// A pest turned up at the edge of the board, ready for next round
+    PestSpawned { entity: Entity, pos: IVec2, wind: bool },
+    /// A pest walked off the board or gave up
+    PestLeftBoard { pos: IVec2 },
+    RoundStarted,
+    RoundEnded { reason: RoundEndReason },
+    /// Prize entry `entry` of the scenario was judged
+    PrizeJudged { entry: usize, tier: PrizeTier, score: u32 },
+}
+
+pub struct GameEventPlugin;
+
+impl Plugin for GameEventPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<GameEvent>();
+        app.add_system_to_stage(CoreStage::PostUpdate, log_game_events.system());
+    }
+}
+
+fn log_game_events(
+    mut events: EventReader<GameEvent>,
+) {
+    for event in events.iter() {
+        debug!("{:?}", event);
+    }
+}
@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+use crate::{
+    GameState,
+    persistence,
+    rng::GameSeed,
+    scenario::Scenario,
+    scoring::PrizeTier,
+    judging::PrizeResults,
+    transitions::Transitions,
+};
+
+const HIGH_SCORES_KEY: &str = "high_scores";
+const RECORDS_PER_SCENARIO: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HighScoreRecord {
+    pub score: u32,
+    pub tier: PrizeTier,
+    pub seed: u64,
+    pub scenario: String,
+    /// Seconds since the unix epoch
+    pub date: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct HighScores {
+    records: Vec<HighScoreRecord>,
+}
+
+impl HighScores {
+    fn load() -> Self {
+        persistence::load(HIGH_SCORES_KEY).unwrap_or_default()
+    }
+
+    fn save(&self) {
+        if let Err(e) = persistence::save(HIGH_SCORES_KEY, self) {
+            warn!("Could not save high scores: {}", e);
+        }
+    }
+
+    /// Adds a record to the table and returns its rank within its scenario,
+    /// or `None` if it didn't score well enough to be kept.
+    pub fn insert(&mut self, record: HighScoreRecord) -> Option<usize> {
+        self.records.push(record.clone());
+        // Stable sort so older records win ties
+        self.records.sort_by(|a, b| b.score.cmp(&a.score).then(b.tier.cmp(&a.tier)));
+
+        let mut kept_per_scenario = std::collections::HashMap::new();
+        self.records.retain(|r| {
+            let kept = kept_per_scenario.entry(r.scenario.clone()).or_insert(0);
+            *kept += 1;
+            *kept <= RECORDS_PER_SCENARIO
+        });
+
+        self.for_scenario(Some(&record.scenario)).position(|r| r == &record)
+    }
+
+    pub fn for_scenario<'a>(&'a self, scenario: Option<&'a str>) -> impl Iterator<Item=&'a HighScoreRecord> + 'a {
+        self.records.iter().filter(move |r| scenario.map_or(true, |s| r.scenario == s))
+    }
+}
+
+/// The record made by the most recently finished run, kept around so the
+/// end screen and the high score table can point it out.
+#[derive(Default)]
+pub struct LatestHighScore {
+    pub record: Option<HighScoreRecord>,
+    pub rank: Option<usize>,
+}
+
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(HighScores::load());
+        app.init_resource::<LatestHighScore>();
+        app.add_system_set(
+            SystemSet::on_enter(GameState::PrizePlantScoring)
+                .with_system(record_high_score.system())
+        );
+        app.add_system_set(
+            SystemSet::on_update(GameState::HighScores)
+                .with_system(high_score_ui.system())
+        );
+    }
+}
+
+fn record_high_score(
+    results: Res<PrizeResults>,
+    seed: Res<GameSeed>,
+    scenario: Res<Scenario>,
+    mut high_scores: ResMut<HighScores>,
+    mut latest: ResMut<LatestHighScore>,
+) {
+    let record = HighScoreRecord {
+        score: results.total_score(),
+        tier: results.best_tier(),
+        seed: seed.0,
+        scenario: scenario.id.to_string(),
+        date: persistence::now(),
+    };
+    latest.rank = high_scores.insert(record.clone());
+    latest.record = Some(record);
+    high_scores.save();
+}
+
+fn high_score_ui(
+    egui_context: Res<EguiContext>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    high_scores: Res<HighScores>,
+    latest: Res<LatestHighScore>,
+    mut filter: Local<Option<String>>,
+    mut transitions: ResMut<Transitions>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        transitions.pop_game();
+        return;
+    }
+    egui::CentralPanel::default().show(egui_context.ctx(), |ui| {
+        ui.heading("High Scores");
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut *filter, None, "All");
+            for scenario in Scenario::all() {
+                ui.selectable_value(&mut *filter, Some(scenario.id.to_string()), scenario.name);
+            }
+        });
+        ui.separator();
+        egui::Grid::new("high_scores").striped(true).show(ui, |ui| {
+            for heading in &["#", "Score", "Prize", "Scenario", "Seed", "Date"] {
+                ui.strong(heading);
+            }
+            ui.end_row();
+            for (i, record) in high_scores.for_scenario(filter.as_deref()).enumerate() {
+                let color = if latest.record.as_ref() == Some(record) {
+                    egui::Color32::YELLOW
+                } else {
+                    ui.visuals().text_color()
+                };
+                let scenario_name = Scenario::by_id(&record.scenario).map_or(record.scenario.as_str(), |s| s.name);
+                ui.colored_label(color, i + 1);
+                ui.colored_label(color, record.score);
+                ui.colored_label(color, record.tier.name());
+                ui.colored_label(color, scenario_name);
+                ui.colored_label(color, record.seed);
+                ui.colored_label(color, format_date(record.date));
+                ui.end_row();
+            }
+        });
+        ui.separator();
+        if ui.button("Back").clicked() {
+            transitions.pop_game();
+        }
+    });
+}
+
+/// Formats unix seconds as `YYYY-MM-DD` (UTC) without pulling in a date library.
+fn format_date(secs: u64) -> String {
+    // Civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
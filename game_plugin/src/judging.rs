@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use bevy::prelude::*;
+use crate::{
+    turn_structure::{RunOver, TurnState},
+    scenario::{PrizeEntry, Scenario},
+    scoring::PrizeTier,
+    plants::{Health, PrizePlant, RoundsTillMature},
+    save_game::new_run,
+    events::GameEvent,
+};
+
+pub struct PrizeJudgement {
+    pub entry: &'static str,
+    pub score: u32,
+    pub max_score: u32,
+    pub tier: PrizeTier,
+}
+
+impl PrizeJudgement {
+    pub fn new(entry: &PrizeEntry, score: u32) -> Self {
+        PrizeJudgement {
+            entry: entry.name,
+            score,
+            max_score: entry.max_score(),
+            tier: entry.thresholds.tier(score),
+        }
+    }
+}
+
+/// One slot per prize entry in the current scenario, filled in as each
+/// entry is judged.
+#[derive(Default)]
+pub struct PrizeResults(pub Vec<Option<PrizeJudgement>>);
+
+impl PrizeResults {
+    pub fn total_score(&self) -> u32 {
+        self.judged().map(|j| j.score).sum()
+    }
+
+    pub fn best_tier(&self) -> PrizeTier {
+        self.judged().map(|j| j.tier).max().unwrap_or(PrizeTier::NoPrize)
+    }
+
+    pub fn judged(&self) -> impl Iterator<Item=&PrizeJudgement> {
+        self.0.iter().filter_map(|j| j.as_ref())
+    }
+
+    fn all_judged(&self) -> bool {
+        self.0.iter().all(|j| j.is_some())
+    }
+}
+
+pub struct JudgingPlugin;
+
+impl Plugin for JudgingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PrizeResults>();
+        app.add_system_set(
+            new_run()
+                .with_system(reset_prize_results.system())
+        );
+        app.add_system_set(
+            SystemSet::on_enter(TurnState::EndOfRound)
+                .with_system(judge_prize_entries.system().after("incr_maturity"))
+        );
+    }
+}
+
+fn reset_prize_results(
+    scenario: Res<Scenario>,
+    mut results: ResMut<PrizeResults>,
+) {
+    results.0.clear();
+    results.0.resize_with(scenario.prize_entries.len(), || None);
+}
+
+fn judge_prize_entries(
+    mut commands: Commands,
+    scenario: Res<Scenario>,
+    mut results: ResMut<PrizeResults>,
+    mut run_over: ResMut<RunOver>,
+    mut events: EventWriter<GameEvent>,
+    plant_query: Query<(Entity, &PrizePlant, &Health, &RoundsTillMature)>,
+) {
+    let mut entries: HashMap<usize, Vec<(Entity, &Health, &RoundsTillMature)>> = HashMap::new();
+    for (e, prize_plant, health, rounds_till_mature) in plant_query.iter() {
+        entries.entry(prize_plant.0).or_default().push((e, health, rounds_till_mature));
+    }
+
+    for (i, entry) in scenario.prize_entries.iter().enumerate() {
+        if results.0[i].is_some() {
+            continue
+        }
+        let plants = entries.remove(&i).unwrap_or_default();
+        // An entry is judged as soon as any part of it matures, or
+        // immediately if the pests have eaten all of it.
+        if !plants.is_empty() && plants.iter().all(|(_, _, r)| r.0 > 0) {
+            continue
+        }
+        let score = plants.iter().map(|(_, h, _)| h.0.max(0) as u32).sum();
+        for (e, _, _) in &plants {
+            commands.entity(*e).despawn_recursive();
+        }
+        let judgement = PrizeJudgement::new(entry, score);
+        info!("{} judged: {} ({}/{})", judgement.entry, judgement.tier.name(), judgement.score, judgement.max_score);
+        events.send(GameEvent::PrizeJudged { entry: i, tier: judgement.tier, score });
+        results.0[i] = Some(judgement);
+    }
+
+    run_over.0 = results.all_judged();
+}
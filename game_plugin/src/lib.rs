@@ -6,6 +6,23 @@ mod plants;
 mod turn_structure;
 mod main_ui;
 mod scoring;
+mod persistence;
+mod rng;
+mod scenario;
+mod high_scores;
+mod judging;
+mod replay;
+mod settings;
+mod pause;
+mod save_game;
+mod animation;
+mod effects;
+mod transitions;
+mod events;
+mod music;
+mod sound;
+#[cfg(target_arch = "wasm32")]
+mod web_touch;
 
 use crate::{
     loading::LoadingPlugin,
@@ -16,6 +33,20 @@ use crate::{
     turn_structure::TurnPlugin,
     main_ui::MainUiPlugin,
     scoring::ScoringPlugin,
+    rng::RngPlugin,
+    scenario::{Difficulty, Scenario},
+    high_scores::HighScorePlugin,
+    judging::JudgingPlugin,
+    replay::ReplayPlugin,
+    settings::SettingsPlugin,
+    pause::PausePlugin,
+    save_game::SaveGamePlugin,
+    animation::AnimationPlugin,
+    effects::EffectsPlugin,
+    transitions::TransitionPlugin,
+    events::GameEventPlugin,
+    music::MusicDirectorPlugin,
+    sound::SoundPlugin,
 };
 
 use game_music::MusicPlugin;
@@ -26,11 +57,16 @@ use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::prelude::*;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
-enum GameState {
+pub enum GameState {
     Loading,
     Playing,
+    Paused,
     PrizePlantScoring,
     Menu,
+    NewGame,
+    Settings,
+    HighScores,
+    Credits,
 }
 
 pub struct GamePlugin;
@@ -38,6 +74,8 @@ pub struct GamePlugin;
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_state(GameState::Loading)
+            .init_resource::<Scenario>()
+            .init_resource::<Difficulty>()
             .add_plugin(bevy_egui::EguiPlugin)
             .add_plugin(MainUiPlugin)
             .add_plugin(LoadingPlugin)
@@ -45,9 +83,25 @@ impl Plugin for GamePlugin {
             .add_plugin(PestPlugin)
             .add_plugin(PlantPlugin)
             .add_plugin(TurnPlugin)
+            // Settings go first, they decide whether audio starts at all
+            .add_plugin(SettingsPlugin)
             .add_plugin(MusicPlugin)
+            .add_plugin(MusicDirectorPlugin)
+            .add_plugin(SoundPlugin)
             .add_plugin(ScoringPlugin)
+            .add_plugin(RngPlugin)
+            .add_plugin(HighScorePlugin)
+            .add_plugin(JudgingPlugin)
+            .add_plugin(ReplayPlugin)
+            .add_plugin(PausePlugin)
+            .add_plugin(SaveGamePlugin)
+            .add_plugin(AnimationPlugin)
+            .add_plugin(EffectsPlugin)
+            .add_plugin(TransitionPlugin)
+            .add_plugin(GameEventPlugin)
             .add_plugin(MapPlugin);
+        #[cfg(target_arch = "wasm32")]
+        app.add_plugin(web_touch::WebTouchPlugin);
 
     }
 }
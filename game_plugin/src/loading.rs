@@ -1,6 +1,7 @@
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_asset_loader::{AssetCollection, AssetLoader};
+use game_music::SynthPatch;
 
 pub struct LoadingPlugin;
 
@@ -28,6 +29,12 @@ pub struct FontAssets {
 
 #[derive(AssetCollection)]
 pub struct AudioAssets {
+    #[asset(path = "music/menu.synth")]
+    pub menu_music: Handle<SynthPatch>,
+    #[asset(path = "music/playing.synth")]
+    pub playing_music: Handle<SynthPatch>,
+    #[asset(path = "music/scoring.synth")]
+    pub scoring_music: Handle<SynthPatch>,
 }
 
 #[derive(AssetCollection)]
@@ -1,21 +1,32 @@
 use bevy::{
     prelude::*,
     asset::HandleId,
+    ecs::schedule::ShouldRun,
     input::{
         ElementState,
-        mouse::MouseButtonInput
+        mouse::{MouseButtonInput, MouseWheel},
+        touch::{TouchInput, TouchPhase},
     }
 };
+use std::collections::{HashMap, HashSet};
 use rand::prelude::*;
 use anyhow::Result;
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     GameState,
-    map::{MAP_SIZE, TILE_SIZE, GameLayer, Fence, TilePos},
+    events::GameEvent,
+    map::{MAP_SIZE, TILE_SIZE, CameraView, GameLayer, Fence, FencePreview, TilePos, fence_autotile_index, tile_to_world, world_to_tile},
     plants::{Plant, RoundsTillMature, Health},
     pests::Pest,
     loading::TextureAssets,
-    turn_structure::TurnState,
+    turn_structure::{LastRoundEnd, TurnState},
+    rng::GameRng,
+    replay::{ReplayAction, ReplayRecorder},
+    save_game::new_run,
+    scenario::{Difficulty, Scenario},
+    transitions::Transitions,
 };
 
 pub struct MainUiPlugin;
@@ -24,47 +35,118 @@ impl Plugin for MainUiPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<CursorPosition>();
         app.init_resource::<TileQueue>();
-        app.init_resource::<FirstClickSupressor>();
+        app.init_resource::<HeldTile>();
+        app.init_resource::<SelectedPlacable>();
+        app.init_resource::<PlacementRotation>();
+        app.init_resource::<GridCursor>();
+        app.init_resource::<GhostPlacement>();
         app.init_resource::<PendingPlacement>();
-        app.add_system(track_cursor.system());
+        app.add_system(track_cursor.system().after("track touches"));
+        app.add_system(track_click_events.system().after("track touches"));
+        app.init_resource::<LastTouch>();
+        app.add_system(track_touches.system().label("track touches"));
+        app.add_system_set(
+            new_run()
+                .with_system(setup_queue.system().after("reseed rng"))
+        );
         app.add_system_set(
             SystemSet::on_enter(GameState::Playing)
-                .with_system(setup_queue.system())
                 .with_system(spawn_overlay.system())
+                .with_system(spawn_hover_highlight.system())
+                .with_system(spawn_hold_slot.system())
         );
         app.add_system_set(
             SystemSet::on_exit(GameState::Playing)
                 .with_system(cleanup_queue.system())
                 .with_system(despawn_overlay.system())
                 .with_system(despawn_tiles.system())
-                .with_system(reset_first_click_supressor.system())
+                .with_system(despawn_hover_highlight.system())
+                .with_system(despawn_hold_slot.system())
         );
         app.add_system_set(
             SystemSet::on_update(GameState::Playing)
-                .with_system(quit_to_menu.system())
-                .with_system(track_click_events.system())
+                .with_system(update_hover_highlight.system().after("move grid cursor"))
         );
         app.add_system_set(
-            SystemSet::on_update(TurnState::PlayerTurn)
-                .with_system(place_tile.system().chain(spawn_tile_sprites.system()))
+            SystemSet::on_update(GameState::Playing)
+                .with_system(move_grid_cursor.system().label("move grid cursor"))
         );
         app.add_system_set(
-            SystemSet::on_enter(TurnState::PlayerTurn)
-                .with_system(arrange_placables.system())
+            player_turn()
+                .with_system(arrange_placables.system().after("manipulate queue"))
+                .with_system(update_ghost_preview.system().after("update ghost"))
+                .with_system(round_end_banner.system())
+        );
+        app.add_system_set(
+            player_input()
+                .with_system(confirm_grid_cursor.system().label("confirm grid cursor").after("move grid cursor"))
+                .with_system(manipulate_queue.system().label("manipulate queue").after("confirm grid cursor"))
+                .with_system(place_tile.system().chain(spawn_tile_sprites.system()).after("manipulate queue"))
+                .with_system(rotate_placement.system().label("rotate placement"))
+                .with_system(update_ghost.system().label("update ghost").after("manipulate queue").after("rotate placement").after("move grid cursor"))
         );
         app.add_system_set(
             SystemSet::on_exit(TurnState::PlayerTurn)
+                .with_system(reset_hold.system())
                 .with_system(arrange_placables.system())
+                .with_system(despawn_ghost.system())
         );
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-enum PlacableTile {
+/// Systems that run during the player's turn, paused or not. Only one set
+/// may use this, it labels the criteria `player_input` pipes from.
+fn player_turn() -> SystemSet {
+    SystemSet::new().with_run_criteria(State::on_update(TurnState::PlayerTurn).label("player turn"))
+}
+
+/// Systems that read the player's input during their turn. `TurnState` runs
+/// on regardless of `GameState`, so these also check that the game isn't
+/// paused under a menu that wants the same keys.
+fn player_input() -> SystemSet {
+    SystemSet::new().with_run_criteria(RunCriteria::pipe("player turn", unless_paused.system()))
+}
+
+fn unless_paused(
+    In(player_turn): In<ShouldRun>,
+    state: Res<State<GameState>>,
+) -> ShouldRun {
+    if state.current() == &GameState::Playing {
+        return player_turn;
+    }
+    match player_turn {
+        ShouldRun::YesAndCheckAgain | ShouldRun::NoAndCheckAgain => ShouldRun::NoAndCheckAgain,
+        ShouldRun::Yes | ShouldRun::No => ShouldRun::No,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlacableTile {
     Radish,
     Carrot,
     Pumpkin,
     Fence,
+    /// Three fences in a row
+    FenceLine,
+    /// Three fences bent into an L
+    FenceCorner,
+    /// Four fences in a T
+    FenceTee,
+    /// Four pumpkins in a square
+    PumpkinPatch,
+}
+
+/// Quarter turns clockwise applied to the footprint of the next placement.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlacementRotation(pub u8);
+
+fn rotate(offset: IVec2, quarter_turns: u8) -> IVec2 {
+    (0..quarter_turns % 4).fold(offset, |o, _| IVec2::new(o.y, -o.x))
+}
+
+/// Whether a tile is inside the garden, where things can be placed.
+pub fn in_garden(pos: IVec2) -> bool {
+    pos.x > 1 && pos.x < MAP_SIZE-2 && pos.y > 1 && pos.y < MAP_SIZE-2
 }
 
 impl PlacableTile {
@@ -74,109 +156,156 @@ impl PlacableTile {
             PlacableTile::Carrot,
             PlacableTile::Pumpkin,
             PlacableTile::Fence,
+            PlacableTile::FenceLine,
+            PlacableTile::FenceCorner,
+            PlacableTile::FenceTee,
+            PlacableTile::PumpkinPatch,
         ].choose(rng).unwrap();
-        let e = commands.spawn_bundle(SpriteBundle {
-            material: materials.add(pt.texture_handle(&textures).into()),
-            transform: Transform::from_xyz(0.0, 0.0, 1.0),
-            ..Default::default()
-        }).insert(pt).id();
-        e
+        pt.spawn(commands, textures, materials)
+    }
+
+    /// Spawns the queue icon. Pieces of several tiles are drawn at half size
+    /// so they fit in a queue slot.
+    pub fn spawn(self, commands: &mut Commands, textures: &TextureAssets, materials: &mut Assets<ColorMaterial>) -> Entity {
+        let cells = self.cells(PlacementRotation(0));
+        if let [(_, single, _)] = cells.as_slice() {
+            return commands.spawn_bundle(SpriteBundle {
+                material: materials.add(single.texture_handle(&textures).into()),
+                transform: Transform::from_xyz(0.0, 0.0, 1.0),
+                ..Default::default()
+            }).insert(self).id();
+        }
+        let center = cells.iter().fold(Vec2::ZERO, |sum, (offset, _, _)| sum + Vec2::new(offset.x as f32, offset.y as f32)) / cells.len() as f32;
+        commands.spawn()
+            .insert(Transform::from_xyz(0.0, 0.0, 1.0))
+            .insert(GlobalTransform::default())
+            .insert(self)
+            .with_children(|parent| {
+                for (offset, single, _) in &cells {
+                    let position = (Vec2::new(offset.x as f32, offset.y as f32) - center) * TILE_SIZE as f32 / 2.0;
+                    parent.spawn_bundle(SpriteBundle {
+                        material: materials.add(single.texture_handle(&textures).into()),
+                        transform: Transform {
+                            translation: position.extend(0.0),
+                            scale: Vec3::splat(0.5),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                }
+            })
+            .id()
     }
 
     fn texture_handle(&self, assets: &TextureAssets) -> Handle<Texture> {
         match self {
             PlacableTile::Radish=> assets.radish.clone(),
             PlacableTile::Carrot => assets.carrot.clone(),
-            PlacableTile::Pumpkin => assets.pumpkin.clone(),
-            PlacableTile::Fence => assets.fence.clone(),
+            PlacableTile::Pumpkin |
+            PlacableTile::PumpkinPatch => assets.pumpkin.clone(),
+            PlacableTile::Fence |
+            PlacableTile::FenceLine |
+            PlacableTile::FenceCorner |
+            PlacableTile::FenceTee => assets.fence.clone(),
         }
     }
 
-    fn can_place(&self, pos: TilePos) -> bool {
-        /*
+    /// The single tiles this placable is made of, as offsets from the tile
+    /// it is placed on, along with the sprite each of them gets.
+    pub fn cells(&self, rotation: PlacementRotation) -> Vec<(IVec2, PlacableTile, &'static str)> {
+        let fences = |offsets: &[(i32, i32)]| offsets.iter()
+            .map(|(x, y)| (rotate(IVec2::new(*x, *y), rotation.0), PlacableTile::Fence, "fence"))
+            .collect();
         match self {
-            PlacableTile::Radish |
-            PlacableTile::Carrot |
-            PlacableTile::Pumpkin => {
-                map_query.get_tile_entity(
-                    pos,
-                    0u16,
-                    GameLayer::Fences
-                ).is_err() &&
-                map_query.get_tile_entity(
-                    pos,
-                    0u16,
-                    GameLayer::Plants
-                ).is_err()
-            }
-            PlacableTile::Fence => {
-                map_query.get_tile_entity(
-                    pos,
-                    0u16,
-                    GameLayer::Plants
-                ).is_err()
-            }
+            PlacableTile::Radish => vec![(IVec2::ZERO, PlacableTile::Radish, "radish")],
+            PlacableTile::Carrot => vec![(IVec2::ZERO, PlacableTile::Carrot, "carrot")],
+            PlacableTile::Pumpkin => vec![(IVec2::ZERO, PlacableTile::Pumpkin, "pumpkin")],
+            PlacableTile::Fence => vec![(IVec2::ZERO, PlacableTile::Fence, "fence")],
+            PlacableTile::FenceLine => fences(&[(-1, 0), (0, 0), (1, 0)]),
+            PlacableTile::FenceCorner => fences(&[(0, 1), (0, 0), (1, 0)]),
+            PlacableTile::FenceTee => fences(&[(-1, 0), (0, 0), (1, 0), (0, -1)]),
+            // A square looks the same whichever way it is turned, and the
+            // big pumpkin art only fits together one way round.
+            PlacableTile::PumpkinPatch => vec![
+                (IVec2::new(0, 0), PlacableTile::Pumpkin, "big_pumpkin1"),
+                (IVec2::new(1, 0), PlacableTile::Pumpkin, "big_pumpkin2"),
+                (IVec2::new(1, 1), PlacableTile::Pumpkin, "big_pumpkin3"),
+                (IVec2::new(0, 1), PlacableTile::Pumpkin, "big_pumpkin4"),
+            ],
         }
-        */
-        true
-    }
-
-    fn place_on_map(&self, commands: &mut Commands, pos: TilePos) -> (Entity, String) {
-        let mut e = commands.spawn();
-        let (spawner, sprite) = match self {
-            PlacableTile::Radish => {
-                (
-                e.insert(GameLayer::Plants)
-                 .insert(Plant(1))
-                 .insert(Health(1))
-                 .insert(RoundsTillMature(1)),
-                 "radish".to_string()
-                )
-            }
-            PlacableTile::Carrot => {
-                (
-                e.insert(GameLayer::Plants)
-                 .insert(Plant(2))
-                 .insert(Health(1))
-                 .insert(RoundsTillMature(2)),
-                "carrot".to_string()
-                )
-            }
-            PlacableTile::Pumpkin => {
-                (
-                e.insert(GameLayer::Plants)
-                 .insert(Plant(6))
-                 .insert(Health(1))
-                 .insert(RoundsTillMature(4)),
-                "pumpkin".to_string()
-                )
-            }
-            PlacableTile::Fence => {
-                (
-                e.insert(GameLayer::Fences)
-                 .insert(Health(1))
-                 .insert(Fence),
-                 "fence".to_string()
-                )
+    }
+
+    /// Every tile of the footprint has to be inside the garden and free.
+    pub fn can_place(&self, pos: TilePos, rotation: PlacementRotation, occupied: &HashSet<IVec2>) -> bool {
+        self.cells(rotation).iter().all(|(offset, _, _)| {
+            let cell = pos.0 + *offset;
+            in_garden(cell) && !occupied.contains(&cell)
+        })
+    }
+
+    fn place_on_map(&self, commands: &mut Commands, pos: TilePos, rotation: PlacementRotation) -> Vec<(Entity, String)> {
+        self.cells(rotation).into_iter().map(|(offset, single, sprite)| {
+            let mut e = commands.spawn();
+            match single {
+                PlacableTile::Radish => {
+                    e.insert(GameLayer::Plants)
+                     .insert(Plant(1))
+                     .insert(Health(1))
+                     .insert(RoundsTillMature(1));
+                }
+                PlacableTile::Carrot => {
+                    e.insert(GameLayer::Plants)
+                     .insert(Plant(2))
+                     .insert(Health(1))
+                     .insert(RoundsTillMature(2));
+                }
+                PlacableTile::Pumpkin => {
+                    e.insert(GameLayer::Plants)
+                     .insert(Plant(6))
+                     .insert(Health(1))
+                     .insert(RoundsTillMature(4));
+                }
+                PlacableTile::Fence => {
+                    e.insert(GameLayer::Fences)
+                     .insert(Health(1))
+                     .insert(Fence);
+                }
+                _ => unreachable!("cells are always single tiles"),
             }
-        };
-        (spawner.insert(pos).id(), sprite)
+            (e.insert(TilePos(pos.0 + offset)).id(), sprite.to_string())
+        }).collect()
     }
 }
 
 #[derive(Default)]
-struct TileQueue(Vec<Entity>);
+pub struct TileQueue(pub Vec<Entity>);
+
+/// The tile in the hold slot. Only one swap is allowed per turn.
+#[derive(Default)]
+pub struct HeldTile {
+    pub tile: Option<Entity>,
+    pub swapped_this_turn: bool,
+}
+
+/// Index into `TileQueue` of the tile that will be placed next. Always 0
+/// unless the run lets the player pick any tile.
+#[derive(Default)]
+pub struct SelectedPlacable(pub usize);
 
 fn setup_queue(
     mut commands: Commands,
     mut queue: ResMut<TileQueue>,
     textures: Res<TextureAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+    mut held: ResMut<HeldTile>,
+    mut selected: ResMut<SelectedPlacable>,
+    mut rng: ResMut<GameRng>,
 ) {
-    let mut rng = thread_rng();
+    *held = HeldTile::default();
+    selected.0 = 0;
     queue.0.clear();
     for _ in 0..5 {
-        let e = PlacableTile::spawn_random(&mut commands, &textures, &mut materials, &mut rng);
+        let e = PlacableTile::spawn_random(&mut commands, &textures, &mut materials, &mut rng.0);
         queue.0.push(e);
     }
 }
@@ -184,27 +313,192 @@ fn setup_queue(
 fn cleanup_queue(
     mut commands: Commands,
     mut queue: ResMut<TileQueue>,
+    mut held: ResMut<HeldTile>,
     query: Query<Entity, With<PlacableTile>>,
 ) {
     for e in query.iter() {
         commands.entity(e).despawn_recursive();
     }
     queue.0.clear();
+    held.tile = None;
+}
+
+fn queue_slot_position(i: usize) -> Vec2 {
+    Vec2::new(
+        i as f32 * TILE_SIZE as f32 * 2.61 - (TILE_SIZE as f32 * MAP_SIZE as f32)/2.0 + TILE_SIZE as f32,
+        (TILE_SIZE as f32 * MAP_SIZE as f32)/2.0,
+    )
+}
+
+/// The hold slot sits just right of the board, level with the queue.
+fn hold_slot_position() -> Vec2 {
+    Vec2::new(
+        (TILE_SIZE as f32 * MAP_SIZE as f32)/2.0 + TILE_SIZE as f32 * 1.5,
+        (TILE_SIZE as f32 * MAP_SIZE as f32)/2.0,
+    )
+}
+
+/// Slots react to a two tile wide square around them so they are easy to hit
+/// with a finger. Queue slots are further apart than that, and the queue is
+/// checked before the hold slot where they meet.
+fn slot_contains(slot: Vec2, point: Vec2) -> bool {
+    let d = (point - slot).abs();
+    d.x < TILE_SIZE as f32 && d.y < TILE_SIZE as f32
+}
+
+/// Background of the hold slot, so it can be seen while it's empty.
+struct HoldSlotFrame;
+
+fn spawn_hold_slot(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    scenario: Res<Scenario>,
+    difficulty: Res<Difficulty>,
+) {
+    if !scenario.queue_rules_for(*difficulty).hold_slot {
+        return;
+    }
+    commands.spawn_bundle(SpriteBundle {
+        material: materials.add(Color::rgba(1.0, 1.0, 1.0, 0.2).into()),
+        sprite: Sprite::new(Vec2::splat(TILE_SIZE as f32 * 2.0)),
+        transform: Transform::from_translation(hold_slot_position().extend(0.5)),
+        ..Default::default()
+    }).insert(HoldSlotFrame);
+}
+
+fn despawn_hold_slot(
+    mut commands: Commands,
+    query: Query<Entity, With<HoldSlotFrame>>,
+) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn on_any_slot(point: Vec2) -> bool {
+    slot_contains(hold_slot_position(), point) || (0..5).any(|i| slot_contains(queue_slot_position(i), point))
 }
 
 fn arrange_placables(
     queue: Res<TileQueue>,
+    held: Res<HeldTile>,
+    selected: Res<SelectedPlacable>,
+    scenario: Res<Scenario>,
+    difficulty: Res<Difficulty>,
     mut query: Query<&mut Transform, With<PlacableTile>>,
 ) {
+    let pick_any = scenario.queue_rules_for(*difficulty).pick_any;
     for (i, e) in queue.0.iter().enumerate() {
         if let Ok(mut t) = query.get_mut(*e) {
-            t.translation.x = i as f32 * TILE_SIZE as f32 * 2.61 - (TILE_SIZE as f32 * MAP_SIZE as f32)/2.0 + TILE_SIZE as f32;
-            t.translation.y = (TILE_SIZE as f32 * MAP_SIZE as f32)/2.0;
+            let position = queue_slot_position(i);
+            t.translation.x = position.x;
+            t.translation.y = position.y;
+            // Make the pick stand out when it isn't simply the first tile
+            t.scale = if pick_any && i == selected.0 { Vec3::splat(1.2) } else { Vec3::ONE };
+        }
+    }
+    if let Some(mut t) = held.tile.and_then(|e| query.get_mut(e).ok()) {
+        let position = hold_slot_position();
+        t.translation.x = position.x;
+        t.translation.y = position.y;
+        t.scale = Vec3::ONE;
+    }
+}
+
+/// Cleared as the turn ends rather than as the next one starts, so a game
+/// saved and restored mid turn keeps the swap it already used.
+fn reset_hold(
+    mut held: ResMut<HeldTile>,
+) {
+    held.swapped_this_turn = false;
+}
+
+const PICK_KEYS: [KeyCode; 5] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5];
+
+/// Handles picking a queued tile and swapping with the hold slot, for runs
+/// whose rules allow it. Clicks on the queue or the hold slot are taken here
+/// so `place_tile` never sees them.
+fn manipulate_queue(
+    mut commands: Commands,
+    textures: Res<TextureAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    scenario: Res<Scenario>,
+    difficulty: Res<Difficulty>,
+    mut pending_placement: ResMut<PendingPlacement>,
+    mut queue: ResMut<TileQueue>,
+    mut held: ResMut<HeldTile>,
+    mut selected: ResMut<SelectedPlacable>,
+    mut rng: ResMut<GameRng>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    let rules = scenario.queue_rules_for(*difficulty);
+    let click = pending_placement.0;
+
+    if rules.pick_any {
+        let mut pick = PICK_KEYS.iter().position(|key| keyboard_input.just_pressed(*key));
+        let len = queue.0.len().max(1);
+        if keyboard_input.just_pressed(KeyCode::Q) || gamepad_just_pressed(&gamepad_buttons, GamepadButtonType::LeftTrigger) {
+            pick = Some((selected.0 + len - 1) % len);
+        }
+        if keyboard_input.just_pressed(KeyCode::E) || gamepad_just_pressed(&gamepad_buttons, GamepadButtonType::RightTrigger) {
+            pick = Some((selected.0 + 1) % len);
+        }
+        if let Some(click) = click {
+            if let Some(i) = (0..queue.0.len()).find(|i| slot_contains(queue_slot_position(*i), click)) {
+                pending_placement.0 = None;
+                pick = Some(i);
+            }
+        }
+        if let Some(i) = pick {
+            if i < queue.0.len() && i != selected.0 {
+                selected.0 = i;
+                recorder.record(ReplayAction::Select(i));
+            }
+        }
+    }
+
+    if rules.hold_slot {
+        let mut hold = keyboard_input.just_pressed(KeyCode::H)
+            || gamepad_just_pressed(&gamepad_buttons, GamepadButtonType::West);
+        if let Some(click) = click {
+            if slot_contains(hold_slot_position(), click) {
+                pending_placement.0 = None;
+                hold = true;
+            }
+        }
+        if hold && !held.swapped_this_turn && selected.0 < queue.0.len() {
+            let current = queue.0.remove(selected.0);
+            match held.tile.replace(current) {
+                Some(previous) => queue.0.insert(selected.0, previous),
+                None => {
+                    let e = PlacableTile::spawn_random(&mut commands, &textures, &mut materials, &mut rng.0);
+                    queue.0.push(e);
+                }
+            }
+            held.swapped_this_turn = true;
+            recorder.record(ReplayAction::Hold);
         }
     }
 }
 
 pub struct GameOverlay;
+/// Tells the player why the last round finished while they plan the next.
+fn round_end_banner(
+    egui_context: Res<EguiContext>,
+    last_round_end: Res<LastRoundEnd>,
+) {
+    if let Some(reason) = last_round_end.0 {
+        egui::Area::new("round_end")
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 10.0])
+            .interactable(false)
+            .show(egui_context.ctx(), |ui| {
+                ui.label(reason.describe());
+            });
+    }
+}
+
 fn spawn_overlay(
     mut commands: Commands,
     textures: Res<TextureAssets>,
@@ -232,88 +526,485 @@ pub fn despawn_overlay(
     }
 }
 
-fn reset_first_click_supressor(
-    mut first_click_supressor: ResMut<FirstClickSupressor>,
+fn track_cursor(
+    mut cursor_moved_events: EventReader<CursorMoved>,
+    mut cursor_position: ResMut<CursorPosition>,
+    mut grid_cursor: ResMut<GridCursor>,
+    time: Res<Time>,
+    last_touch: Res<LastTouch>,
 ) {
-    first_click_supressor.0 = false;
+    for event in cursor_moved_events.iter() {
+        cursor_position.0 = event.position;
+        if !last_touch.recent(&time) {
+            grid_cursor.active = false;
+        }
+    }
+}
+
+/// A tile cursor for playing without a mouse. It takes over from the mouse
+/// as soon as it is moved and hands back when the mouse moves.
+pub struct GridCursor {
+    pub pos: IVec2,
+    pub active: bool,
 }
 
-fn quit_to_menu(
+impl Default for GridCursor {
+    fn default() -> Self {
+        GridCursor {
+            pos: IVec2::splat(MAP_SIZE / 2),
+            active: false,
+        }
+    }
+}
+
+impl GridCursor {
+    /// The tile the next placement is aimed at, from whichever of the grid
+    /// cursor and the mouse is in use.
+    fn target(&self, cursor_position: &CursorPosition, view: &CameraView) -> IVec2 {
+        if self.active {
+            self.pos
+        } else {
+            world_to_tile(view.screen_to_world(cursor_position.0))
+        }
+    }
+}
+
+fn gamepad_just_pressed(gamepad_buttons: &Input<GamepadButton>, button: GamepadButtonType) -> bool {
+    gamepad_buttons.get_just_pressed().any(|b| b.1 == button)
+}
+
+const CURSOR_MOVES: [(KeyCode, KeyCode, GamepadButtonType, (i32, i32)); 4] = [
+    (KeyCode::Up, KeyCode::W, GamepadButtonType::DPadUp, (0, 1)),
+    (KeyCode::Down, KeyCode::S, GamepadButtonType::DPadDown, (0, -1)),
+    (KeyCode::Left, KeyCode::A, GamepadButtonType::DPadLeft, (-1, 0)),
+    (KeyCode::Right, KeyCode::D, GamepadButtonType::DPadRight, (1, 0)),
+];
+
+fn move_grid_cursor(
     keyboard_input: Res<Input<KeyCode>>,
-    mut state: ResMut<State<GameState>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    cursor_position: Res<CursorPosition>,
+    view: Res<CameraView>,
+    mut grid_cursor: ResMut<GridCursor>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::Escape) {
-        state.set(GameState::Menu);
+    let mut step = IVec2::ZERO;
+    for (arrow, wasd, dpad, (dx, dy)) in CURSOR_MOVES.iter() {
+        if keyboard_input.just_pressed(*arrow) || keyboard_input.just_pressed(*wasd) || gamepad_just_pressed(&gamepad_buttons, *dpad) {
+            step += IVec2::new(*dx, *dy);
+        }
+    }
+    if step == IVec2::ZERO {
+        return;
+    }
+    if !grid_cursor.active {
+        // Pick up from wherever the mouse was pointing
+        let mouse_tile = world_to_tile(view.screen_to_world(cursor_position.0));
+        if in_garden(mouse_tile) {
+            grid_cursor.pos = mouse_tile;
+        }
+        grid_cursor.active = true;
     }
+    let pos = grid_cursor.pos + step;
+    grid_cursor.pos = IVec2::new(pos.x.clamp(2, MAP_SIZE - 3), pos.y.clamp(2, MAP_SIZE - 3));
 }
 
-fn track_cursor(
-    mut cursor_moved_events: EventReader<CursorMoved>,
-    mut cursor_position: ResMut<CursorPosition>,
-    windows: Res<Windows>,
+/// Enter, Space or the gamepad's south button place at the grid cursor, the
+/// same way a click places at the mouse.
+fn confirm_grid_cursor(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut grid_cursor: ResMut<GridCursor>,
+    mut pending_placement: ResMut<PendingPlacement>,
 ) {
-    let window = windows.get_primary().unwrap();
-    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
-    for event in cursor_moved_events.iter() {
-        cursor_position.0 = event.position - window_size/2.0;
+    if keyboard_input.just_pressed(KeyCode::Return)
+        || keyboard_input.just_pressed(KeyCode::Space)
+        || gamepad_just_pressed(&gamepad_buttons, GamepadButtonType::South)
+    {
+        grid_cursor.active = true;
+        pending_placement.0 = Some(tile_to_world(grid_cursor.pos));
     }
 }
 
+/// A placement request at a point in world space, from a click, a tap or the
+/// grid cursor.
 #[derive(Default)]
 struct PendingPlacement(Option<Vec2>);
-#[derive(Default)]
-struct FirstClickSupressor(bool);
 fn track_click_events(
     mut mouse_button_input_events: EventReader<MouseButtonInput>,
     cursor_position: Res<CursorPosition>,
+    view: Res<CameraView>,
     mut pending_placement: ResMut<PendingPlacement>,
-    mut first_click_supressor: ResMut<FirstClickSupressor>,
+    state: Res<State<GameState>>,
+    egui_context: Res<EguiContext>,
+    time: Res<Time>,
+    last_touch: Res<LastTouch>,
 ) {
+    // Always drain the events, otherwise the clicks on menus and on the pause
+    // window would be read as placements once the game is running.
     for event in mouse_button_input_events.iter() {
+        if state.current() != &GameState::Playing || egui_context.ctx().wants_pointer_input() || last_touch.recent(&time) {
+            continue
+        }
         if event.button == MouseButton::Left && event.state == ElementState::Released {
-            if !first_click_supressor.0 {
-                first_click_supressor.0 = true;
-                continue
-            }
-            pending_placement.0.replace(cursor_position.0);
+            pending_placement.0.replace(view.screen_to_world(cursor_position.0));
         }
     }
 }
 
+/// The mouse position in window coordinates.
 #[derive(Default)]
 struct CursorPosition(Vec2);
+
+/// How far a finger can wander before a touch counts as a drag, not a tap.
+const TAP_SLOP: f32 = 12.0;
+
+/// When the screen was last touched. Browsers follow a tap with emulated
+/// mouse events, which must not undo the tap's preview or place a tile.
+#[derive(Default)]
+struct LastTouch(f64);
+
+impl LastTouch {
+    fn recent(&self, time: &Time) -> bool {
+        self.0 > 0.0 && time.seconds_since_startup() - self.0 < 0.5
+    }
+}
+
+#[derive(Default)]
+struct TouchGesture {
+    /// Last position of each finger in window coordinates
+    fingers: HashMap<u64, Vec2>,
+    /// Whether the current touch has turned into a drag or a pinch
+    dragging: bool,
+    start: Vec2,
+}
+
+/// Tapping a tile previews the placement there through the grid cursor and
+/// tapping it again confirms. One finger drags the view, two pinch to zoom.
+fn track_touches(
+    mut touch_events: EventReader<TouchInput>,
+    state: Res<State<GameState>>,
+    time: Res<Time>,
+    mut last_touch: ResMut<LastTouch>,
+    mut gesture: Local<TouchGesture>,
+    mut view: ResMut<CameraView>,
+    mut grid_cursor: ResMut<GridCursor>,
+    mut pending_placement: ResMut<PendingPlacement>,
+) {
+    for event in touch_events.iter() {
+        if state.current() != &GameState::Playing {
+            gesture.fingers.clear();
+            continue
+        }
+        last_touch.0 = time.seconds_since_startup();
+        let pos = view.touch_to_window(event.position);
+        match event.phase {
+            TouchPhase::Started => {
+                gesture.fingers.insert(event.id, pos);
+                if gesture.fingers.len() == 1 {
+                    gesture.dragging = false;
+                    gesture.start = pos;
+                } else {
+                    gesture.dragging = true;
+                }
+            }
+            TouchPhase::Moved => {
+                let last = match gesture.fingers.get(&event.id) {
+                    Some(last) => *last,
+                    None => continue,
+                };
+                let other = gesture.fingers.iter().find(|(id, _)| **id != event.id).map(|(_, p)| *p);
+                if (pos - gesture.start).length() > TAP_SLOP {
+                    gesture.dragging = true;
+                }
+                if let Some(other) = other {
+                    let before = (last - other).length();
+                    let after = (pos - other).length();
+                    if before > 0.0 && after > 0.0 {
+                        view.zoom_by(before / after);
+                    }
+                    // The view follows the midpoint between the fingers
+                    let scale = view.world_per_pixel();
+                    view.pan_by(-(pos - last) / 2.0 * scale);
+                } else if gesture.dragging {
+                    let scale = view.world_per_pixel();
+                    view.pan_by(-(pos - last) * scale);
+                }
+                gesture.fingers.insert(event.id, pos);
+            }
+            TouchPhase::Ended => {
+                gesture.fingers.remove(&event.id);
+                if gesture.dragging || !gesture.fingers.is_empty() {
+                    continue
+                }
+                let world = view.screen_to_world(pos);
+                if on_any_slot(world) {
+                    pending_placement.0 = Some(world);
+                    continue
+                }
+                let tile = world_to_tile(world);
+                if !in_garden(tile) {
+                    continue
+                }
+                if grid_cursor.active && grid_cursor.pos == tile {
+                    pending_placement.0 = Some(tile_to_world(tile));
+                } else {
+                    grid_cursor.pos = tile;
+                    grid_cursor.active = true;
+                }
+            }
+            TouchPhase::Cancelled => {
+                gesture.fingers.remove(&event.id);
+                gesture.dragging = true;
+            }
+        }
+    }
+}
+
+fn rotate_placement(
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    egui_context: Res<EguiContext>,
+    mut rotation: ResMut<PlacementRotation>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    let mut turns: i32 = 0;
+    for event in mouse_wheel_events.iter() {
+        if egui_context.ctx().wants_pointer_input() {
+            continue
+        }
+        turns += event.y.signum() as i32;
+    }
+    if keyboard_input.just_pressed(KeyCode::R) || gamepad_just_pressed(&gamepad_buttons, GamepadButtonType::North) {
+        turns += 1;
+    }
+    if turns != 0 {
+        rotation.0 = (rotation.0 as i32 + turns).rem_euclid(4) as u8;
+        recorder.record(ReplayAction::Rotate(rotation.0));
+    }
+}
+
+/// Translucent copy of one tile of the next placement, on the board tile it
+/// would land on.
+pub struct Ghost(pub IVec2);
+
+/// What the ghost currently shows: where, which placable and which way round.
+#[derive(Default)]
+struct GhostPlacement(Option<(IVec2, PlacableTile, PlacementRotation)>);
+
+/// Square drawn under the tile the next click or confirm will hit.
+pub struct HoverHighlight;
+
+const GHOST_VALID: Color = Color::rgba(0.5, 1.0, 0.5, 0.6);
+const GHOST_INVALID: Color = Color::rgba(1.0, 0.4, 0.4, 0.6);
+
+fn spawn_hover_highlight(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn_bundle(SpriteBundle {
+        material: materials.add(Color::rgba(1.0, 1.0, 0.6, 0.3).into()),
+        sprite: Sprite::new(Vec2::splat(TILE_SIZE as f32)),
+        transform: Transform::from_xyz(0.0, 0.0, 0.5),
+        ..Default::default()
+    }).insert(HoverHighlight);
+}
+
+fn update_hover_highlight(
+    cursor_position: Res<CursorPosition>,
+    grid_cursor: Res<GridCursor>,
+    view: Res<CameraView>,
+    mut highlight_query: Query<(&mut Transform, &mut Visible), With<HoverHighlight>>,
+) {
+    let target = grid_cursor.target(&cursor_position, &view);
+    for (mut t, mut visible) in highlight_query.iter_mut() {
+        let position = tile_to_world(target);
+        t.translation.x = position.x;
+        t.translation.y = position.y;
+        visible.is_visible = in_garden(target);
+    }
+}
+
+fn despawn_hover_highlight(
+    mut commands: Commands,
+    highlight_query: Query<Entity, With<HoverHighlight>>,
+) {
+    for e in highlight_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn update_ghost(
+    mut commands: Commands,
+    textures: Res<TextureAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    cursor_position: Res<CursorPosition>,
+    grid_cursor: Res<GridCursor>,
+    view: Res<CameraView>,
+    queue: Res<TileQueue>,
+    selected: Res<SelectedPlacable>,
+    rotation: Res<PlacementRotation>,
+    placables_query: Query<&PlacableTile>,
+    ghost_query: Query<Entity, With<Ghost>>,
+    mut shown: ResMut<GhostPlacement>,
+    mut fence_atlas: Local<Option<Handle<TextureAtlas>>>,
+) {
+    let anchor = grid_cursor.target(&cursor_position, &view);
+    let placable = queue.0.get(selected.0).and_then(|e| placables_query.get(*e).ok()).copied();
+    let wanted = placable.filter(|_| in_garden(anchor)).map(|p| (anchor, p, *rotation));
+    // The ghost is despawned between turns, so an unchanged wish still needs
+    // respawning if nothing is showing.
+    if shown.0 == wanted && (wanted.is_none() || ghost_query.iter().next().is_some()) {
+        return;
+    }
+    for e in ghost_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    shown.0 = wanted;
+    if let Some((anchor, placable, rotation)) = wanted {
+        for (offset, single, _) in placable.cells(rotation) {
+            let pos = anchor + offset;
+            let transform = Transform::from_translation(tile_to_world(pos).extend(50.0));
+            if single == PlacableTile::Fence {
+                // Fences use the same tile sheet as placed fences so the
+                // ghost shows how it will join up with its neighbours.
+                let atlas = fence_atlas.get_or_insert_with(|| {
+                    texture_atlases.add(TextureAtlas::from_grid(textures.fence_tiles.clone(), Vec2::new(86.0, 86.0), 4, 4))
+                }).clone();
+                commands.spawn_bundle(SpriteSheetBundle {
+                    texture_atlas: atlas,
+                    transform,
+                    ..Default::default()
+                }).insert(Ghost(pos));
+            } else {
+                commands.spawn_bundle(SpriteBundle {
+                    material: materials.add(ColorMaterial::texture(single.texture_handle(&textures))),
+                    transform,
+                    ..Default::default()
+                }).insert(Ghost(pos));
+            }
+        }
+    }
+}
+
+/// Tints the ghost by whether it can be placed and keeps its fences, and the
+/// fences next to it, joined up with the rest of the board.
+fn update_ghost_preview(
+    shown: Res<GhostPlacement>,
+    tile_query: Query<&TilePos>,
+    mut preview: ResMut<FencePreview>,
+    fence_query: Query<&TilePos, With<Fence>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut ghost_query: Query<(&Ghost, Option<&Handle<ColorMaterial>>, Option<&mut TextureAtlasSprite>)>,
+) {
+    let (valid, ghost_fences) = match shown.0 {
+        Some((anchor, placable, rotation)) => {
+            let occupied: HashSet<IVec2> = tile_query.iter().map(|p| p.0).collect();
+            let fences = placable.cells(rotation).into_iter()
+                .filter(|(_, single, _)| *single == PlacableTile::Fence)
+                .map(|(offset, _, _)| anchor + offset)
+                .collect();
+            (placable.can_place(TilePos(anchor), rotation, &occupied), fences)
+        }
+        None => (false, vec![]),
+    };
+
+    // Only a placement that can actually happen joins up with the board
+    let wanted_preview = if valid { ghost_fences } else { vec![] };
+    if preview.0 != wanted_preview {
+        preview.0 = wanted_preview;
+    }
+
+    let mut fences: HashSet<IVec2> = fence_query.iter().map(|p| p.0).collect();
+    fences.extend(preview.0.iter().copied());
+    let color = if valid { GHOST_VALID } else { GHOST_INVALID };
+    for (ghost, material, sprite) in ghost_query.iter_mut() {
+        if let Some(material) = material.and_then(|m| materials.get_mut(m)) {
+            if material.color != color {
+                material.color = color;
+            }
+        }
+        if let Some(mut sprite) = sprite {
+            sprite.color = color;
+            sprite.index = fence_autotile_index(ghost.0, &fences);
+        }
+    }
+}
+
+fn despawn_ghost(
+    mut commands: Commands,
+    ghost_query: Query<Entity, With<Ghost>>,
+    mut shown: ResMut<GhostPlacement>,
+    mut preview: ResMut<FencePreview>,
+) {
+    for e in ghost_query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    shown.0 = None;
+    preview.0.clear();
+}
 fn place_tile(
     mut commands: Commands,
     textures: Res<TextureAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut queue: ResMut<TileQueue>,
+    mut selected: ResMut<SelectedPlacable>,
+    mut rotation: ResMut<PlacementRotation>,
     placables_query: Query<&PlacableTile>,
-    mut state: ResMut<State<TurnState>>,
+    mut transitions: ResMut<Transitions>,
     mut pending_placement: ResMut<PendingPlacement>,
     collision_query: Query<&TilePos>,
+    mut rng: ResMut<GameRng>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut events: EventWriter<GameEvent>,
 ) -> Result<Vec<(Entity, String)>> {
     let mut to_spawn = vec![];
     if let Some(click_pos) = pending_placement.0.take() {
-        let mut tile = (click_pos / TILE_SIZE as f32 + Vec2::splat(MAP_SIZE as f32 / 2.0)).floor();
-        tile.y += 1.0;
-        if tile.x > 1.0 && tile.x < MAP_SIZE as f32-2.0 && tile.y > 1.0 && tile.y < MAP_SIZE as f32-2.0 {
-            if let Ok(placable_tile) = placables_query.get(queue.0[0]) {
-                let pos = TilePos(IVec2::new(tile.x as i32, tile.y as i32));
-                if !collision_query.iter().any(|other| other == &pos) {
-                    let placable_entity = queue.0.remove(0);
-                    let e = PlacableTile::spawn_random(&mut commands, &textures, &mut materials, &mut thread_rng());
-                    queue.0.push(e);
-                    to_spawn.push(placable_tile.place_on_map(&mut commands, pos));
-                    commands.entity(placable_entity).despawn_recursive();
-                    state.set(TurnState::PestTurnA);
-                }
+        let pos = TilePos(world_to_tile(click_pos));
+        let index = selected.0.min(queue.0.len().saturating_sub(1));
+        if let Some(placable_tile) = queue.0.get(index).and_then(|e| placables_query.get(*e).ok()) {
+            let occupied: HashSet<IVec2> = collision_query.iter().map(|p| p.0).collect();
+            if placable_tile.can_place(pos, *rotation, &occupied) {
+                let placable_entity = queue.0.remove(index);
+                selected.0 = 0;
+                let e = PlacableTile::spawn_random(&mut commands, &textures, &mut materials, &mut rng.0);
+                queue.0.push(e);
+                to_spawn.extend(placable_tile.place_on_map(&mut commands, pos, *rotation));
+                events.send(GameEvent::TilePlaced { tile: *placable_tile, pos: pos.0, rotation: rotation.0 });
+                *rotation = PlacementRotation::default();
+                commands.entity(placable_entity).despawn_recursive();
+                recorder.record(ReplayAction::Place(pos.0));
+                transitions.set_turn(TurnState::PestTurnA);
+            } else if in_garden(pos.0) {
+                events.send(GameEvent::PlacementRejected { pos: pos.0 });
             }
         }
     }
     Ok(to_spawn)
 }
 
-struct DesiredSprite(String);
+/// The name of the sprite a tile was given, kept so the tile can be saved
+/// and respawned with the same look.
+pub struct TileSprite(pub String);
+
+/// Texture for a tile sprite name, if it is one `spawn_tile_sprites` knows.
+pub fn tile_texture(textures: &TextureAssets, name: &str) -> Option<Handle<Texture>> {
+    Some(match name {
+        "radish" => textures.radish.clone(),
+        "carrot" => textures.carrot.clone(),
+        "pumpkin" => textures.pumpkin.clone(),
+        "big_pumpkin1" => textures.big_pumpkin1.clone(),
+        "big_pumpkin2" => textures.big_pumpkin2.clone(),
+        "big_pumpkin3" => textures.big_pumpkin3.clone(),
+        "big_pumpkin4" => textures.big_pumpkin4.clone(),
+        "fence" => textures.fence_tiles.clone(),
+        "rabbit" => textures.rabbit.clone(),
+        "wind" => textures.wind.clone(),
+        _ => return None,
+    })
+}
+
 pub fn spawn_tile_sprites(
     In(to_spawn): In<Result<Vec<(Entity, String)>>>,
     mut commands: Commands,
@@ -323,16 +1014,12 @@ pub fn spawn_tile_sprites(
 ) {
     if let Ok(to_spawn) = to_spawn {
         for (e, desired_sprite) in &to_spawn {
-            let handle = match desired_sprite.as_str() {
-                "radish" => textures.radish.clone(),
-                "carrot" => textures.carrot.clone(),
-                "pumpkin" => textures.pumpkin.clone(),
-                "big_pumpkin1" => textures.big_pumpkin1.clone(),
-                "big_pumpkin2" => textures.big_pumpkin2.clone(),
-                "big_pumpkin3" => textures.big_pumpkin3.clone(),
-                "big_pumpkin4" => textures.big_pumpkin4.clone(),
-                "fence" => textures.fence_tiles.clone(),
-                _ => unimplemented!()
+            let handle = match tile_texture(&textures, desired_sprite) {
+                Some(handle) => handle,
+                None => {
+                    warn!("Unknown tile sprite {:?}, leaving the tile without one", desired_sprite);
+                    continue;
+                }
             };
             if desired_sprite == "fence" {
                 let texture_atlas = TextureAtlas::from_grid(handle, Vec2::new(86.0, 86.0), 4, 4);
@@ -342,14 +1029,14 @@ pub fn spawn_tile_sprites(
                     transform: Transform::from_xyz(0.0, 0.0, 1.0),
                     ..Default::default()
                 })
-                .remove::<DesiredSprite>();
+                .insert(TileSprite(desired_sprite.clone()));
             } else {
                 commands.entity(*e).insert_bundle(SpriteBundle {
                     material: materials.add(handle.into()),
                     transform: Transform::from_xyz(0.0, 0.0, 1.0),
                     ..Default::default()
                 })
-                .remove::<DesiredSprite>();
+                .insert(TileSprite(desired_sprite.clone()));
             }
         }
     }
@@ -364,3 +1051,43 @@ fn despawn_tiles(
         commands.entity(e).despawn_recursive();
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Presses `key` on a turn in `state` and returns where a tile would be
+    /// placed, if anywhere.
+    fn press_during(state: GameState, key: KeyCode) -> Option<Vec2> {
+        let mut app = App::build();
+        app.add_state(state)
+            .add_state(TurnState::PlayerTurn)
+            .init_resource::<GridCursor>()
+            .init_resource::<PendingPlacement>()
+            .init_resource::<Input<GamepadButton>>()
+            .add_system_set(player_turn().with_system(noop.system()))
+            .add_system_set(player_input().with_system(confirm_grid_cursor.system()));
+        let mut keyboard_input = Input::<KeyCode>::default();
+        keyboard_input.press(key);
+        app.insert_resource(keyboard_input);
+        app.app.update();
+        app.world().get_resource::<PendingPlacement>().unwrap().0
+    }
+
+    fn noop() {}
+
+    #[test]
+    fn confirm_places_during_play() {
+        assert!(press_during(GameState::Playing, KeyCode::Return).is_some());
+        assert!(press_during(GameState::Playing, KeyCode::Space).is_some());
+    }
+
+    #[test]
+    fn confirm_does_nothing_while_paused() {
+        for state in [GameState::Paused, GameState::Settings].iter() {
+            assert_eq!(press_during(state.clone(), KeyCode::Return), None);
+            assert_eq!(press_during(state.clone(), KeyCode::Space), None);
+        }
+    }
+}
@@ -1,14 +1,17 @@
 use std::collections::HashSet;
 use crate::{
+    animation::Animated,
     loading::TextureAssets,
     plants::{Plant, RoundsTillMature, Health, PrizePlant},
     turn_structure::TurnState,
-    scoring::PrizePlantScore,
+    scenario::Scenario,
     main_ui::spawn_tile_sprites,
+    save_game::new_run,
     GameState,
 };
 use bevy::prelude::*;
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
 use rand::prelude::*;
 
@@ -22,20 +25,31 @@ pub struct TilePos(pub IVec2);
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<CameraView>();
+        app.init_resource::<FencePreview>();
         app.add_startup_system(spawn_camera.system());
+        app.add_system(apply_camera_view.system());
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            update_camera_mapping.system().after(bevy::transform::TransformSystem::TransformPropagate)
+        );
         app.add_system_to_stage(CoreStage::PostUpdate, update_fence_autotile.system())
            .add_system_to_stage(CoreStage::PostUpdate, update_tile_position.system());
 
         app.add_system_set(
-            SystemSet::on_exit(GameState::Menu)
+            new_run()
                 .with_system(spawn_initial_map.system().chain(spawn_tile_sprites.system()))
         );
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Playing)
+                .with_system(reset_camera_view.system())
+        );
 
     }
 }
 
 #[repr(u16)]
-#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum GameLayer {
     Fences,
     Plants,
@@ -46,12 +60,154 @@ pub struct Blocking;
 
 pub struct Fence;
 
+pub struct MainCamera;
+
+/// World space that always has to fit in the window: the board in its
+/// frame, the queue above it and the hold slot to its right.
+const LAYOUT_WIDTH: f32 = 1060.0;
+const LAYOUT_HEIGHT: f32 = 1050.0;
+
+/// How the board camera looks at the world. `fit` scales the layout into
+/// the window, and on top of that touch screens can pinch to zoom (a zoom
+/// below 1 shows the board larger) and drag to pan.
+pub struct CameraView {
+    pub zoom: f32,
+    pub pan: Vec2,
+    fit: f32,
+    window_size: Vec2,
+    /// Normalized device coordinates to world space, taken from the camera
+    ndc_to_world: Mat4,
+}
+
+impl Default for CameraView {
+    fn default() -> Self {
+        CameraView {
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+            fit: 1.0,
+            window_size: Vec2::ZERO,
+            ndc_to_world: Mat4::IDENTITY,
+        }
+    }
+}
+
+impl CameraView {
+    pub const MIN_ZOOM: f32 = 0.4;
+    pub const MAX_ZOOM: f32 = 1.5;
+
+    /// Converts a point in window coordinates, as given by cursor and touch
+    /// events, into world space.
+    pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+        if self.window_size.x <= 0.0 || self.window_size.y <= 0.0 {
+            return screen;
+        }
+        let ndc = screen / self.window_size * 2.0 - Vec2::ONE;
+        self.ndc_to_world.transform_point3(ndc.extend(0.0)).truncate()
+    }
+
+    /// Converts a touch position into the window coordinates cursor events
+    /// use. Touches have their origin at the top left, except on Android and
+    /// iOS where bevy already flips them to the bottom left.
+    pub fn touch_to_window(&self, touch: Vec2) -> Vec2 {
+        if cfg!(any(target_os = "android", target_os = "ios")) {
+            touch
+        } else {
+            Vec2::new(touch.x, self.window_size.y - touch.y)
+        }
+    }
+
+    /// World units covered by one logical pixel of the window.
+    pub fn world_per_pixel(&self) -> f32 {
+        self.fit * self.zoom
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(CameraView::MIN_ZOOM, CameraView::MAX_ZOOM);
+    }
+
+    /// Moves the view, keeping the middle of the screen over the board.
+    pub fn pan_by(&mut self, delta: Vec2) {
+        let limit = (MAP_SIZE * TILE_SIZE) as f32 / 2.0;
+        let pan = self.pan + delta;
+        self.pan = Vec2::new(pan.x.clamp(-limit, limit), pan.y.clamp(-limit, limit));
+    }
+}
+
 fn spawn_camera(mut commands: Commands) {
-    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d()).insert(MainCamera);
+}
+
+/// The camera projection maps one world unit to one logical pixel, so the
+/// camera's scale is what makes the layout fit whatever size and scale
+/// factor the window has.
+fn apply_camera_view(
+    windows: Res<Windows>,
+    mut view: ResMut<CameraView>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let window_size = Vec2::new(window.width(), window.height());
+    if window_size.x <= 0.0 || window_size.y <= 0.0 {
+        return;
+    }
+    let fit = (LAYOUT_WIDTH / window_size.x).max(LAYOUT_HEIGHT / window_size.y);
+    if view.fit != fit || view.window_size != window_size {
+        view.fit = fit;
+        view.window_size = window_size;
+    }
+    if !view.is_changed() {
+        return;
+    }
+    let scale = view.world_per_pixel();
+    for mut t in camera_query.iter_mut() {
+        t.translation.x = view.pan.x;
+        t.translation.y = view.pan.y;
+        t.scale = Vec3::new(scale, scale, 1.0);
+    }
+}
+
+fn update_camera_mapping(
+    mut view: ResMut<CameraView>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    if let Ok((camera, transform)) = camera_query.single() {
+        let ndc_to_world = transform.compute_matrix() * camera.projection_matrix.inverse();
+        if view.ndc_to_world != ndc_to_world {
+            view.ndc_to_world = ndc_to_world;
+        }
+    }
 }
 
+fn reset_camera_view(
+    mut view: ResMut<CameraView>,
+) {
+    *view = CameraView::default();
+}
+
+
+/// Fence tiles that are about to be placed, so the fences around them
+/// already join up with them.
+#[derive(Default)]
+pub struct FencePreview(pub Vec<IVec2>);
+
+/// Index into the fence tile sheet for a fence at `pos`, given where all the
+/// fences are.
+pub fn fence_autotile_index(pos: IVec2, fences: &HashSet<IVec2>) -> u32 {
+    let mut index = 0;
+    for ((dx, dy), constant) in &[((0, 1), 1), ((0, -1), 8), ((-1, 0), 2), ((1, 0), 4)] {
+        let other = IVec2::new(pos.x+dx, pos.y+dy);
+        if fences.contains(&other) {
+            index += constant;
+        }
+    }
+    index
+}
 
 fn update_fence_autotile(
+    preview: Res<FencePreview>,
     mut tile_query: Query<(&mut TextureAtlasSprite, &TilePos), With<Fence>>,
 ) {
     //TODO: Use and event instead of naive check on each frame
@@ -60,29 +216,45 @@ fn update_fence_autotile(
     for (_, pos) in tile_query.iter_mut() {
         current_positions.insert(pos.0);
     }
+    current_positions.extend(preview.0.iter().copied());
     for (mut sprite, pos) in tile_query.iter_mut() {
-        sprite.index = 0;
-        for ((dx, dy), constant) in &[((0, 1), 1), ((0, -1), 8), ((-1, 0), 2), ((1, 0), 4)] {
-            let other = IVec2::new(pos.0.x+dx, pos.0.y+dy);
-            if current_positions.contains(&other) {
-                sprite.index += constant;
-            }
-        }
+        sprite.index = fence_autotile_index(pos.0, &current_positions);
     }
 }
 
+/// The bottom left corner of tile (0, 0) in world space. The board is drawn
+/// one row lower than it is centred, to leave room for the queue on top.
+fn board_origin() -> Vec2 {
+    Vec2::new(
+        -(MAP_SIZE as f32 * TILE_SIZE as f32) / 2.0,
+        -(MAP_SIZE as f32 * TILE_SIZE as f32) / 2.0 - TILE_SIZE as f32,
+    )
+}
+
+/// The centre of a tile in world space.
+pub fn tile_to_world(pos: IVec2) -> Vec2 {
+    board_origin() + (Vec2::new(pos.x as f32, pos.y as f32) + Vec2::splat(0.5)) * TILE_SIZE as f32
+}
+
+/// The tile a point in world space falls on.
+pub fn world_to_tile(world: Vec2) -> IVec2 {
+    let tile = ((world - board_origin()) / TILE_SIZE as f32).floor();
+    IVec2::new(tile.x as i32, tile.y as i32)
+}
+
 fn update_tile_position(
-    mut query: Query<(&mut Transform, &TilePos)>,
+    mut query: Query<(&mut Transform, &TilePos), Without<Animated>>,
 ) {
     for (mut t, p) in query.iter_mut() {
-        t.translation.x = p.0.x as f32 * TILE_SIZE as f32 - (MAP_SIZE as f32 * TILE_SIZE as f32) / 2.0 + TILE_SIZE as f32 /2.0;
-        t.translation.y = p.0.y as f32 * TILE_SIZE as f32 - (MAP_SIZE as f32 * TILE_SIZE as f32) / 2.0 - TILE_SIZE as f32 /2.0;
+        let position = tile_to_world(p.0);
+        t.translation.x = position.x;
+        t.translation.y = position.y;
     }
 }
 
 fn spawn_initial_map(
     mut commands: Commands,
-    mut prize_plant_score: ResMut<PrizePlantScore>,
+    scenario: Res<Scenario>,
 ) -> Result<Vec<(Entity, String)>> {
     let mut fences = vec![];
     for i in 4..8 {
@@ -100,39 +272,18 @@ fn spawn_initial_map(
          .id();
         to_spawn.push((e, "fence".to_string()))
     }
-    prize_plant_score.0 = 0;
-    let e = commands.spawn().insert(GameLayer::Plants)
-     .insert(Plant(60))
-     .insert(RoundsTillMature(10))
-     .insert(Health(10))
-     .insert(PrizePlant)
-     .insert(TilePos(IVec2::new(5,5)))
-     .id();
-    to_spawn.push((e, "big_pumpkin1".to_string()));
-    let e = commands.spawn().insert(GameLayer::Plants)
-     .insert(Plant(60))
-     .insert(RoundsTillMature(10))
-     .insert(Health(10))
-     .insert(PrizePlant)
-     .insert(TilePos(IVec2::new(6,5)))
-     .id();
-    to_spawn.push((e, "big_pumpkin2".to_string()));
-    let e = commands.spawn().insert(GameLayer::Plants)
-     .insert(Plant(60))
-     .insert(RoundsTillMature(10))
-     .insert(Health(10))
-     .insert(PrizePlant)
-     .insert(TilePos(IVec2::new(6,6)))
-     .id();
-    to_spawn.push((e, "big_pumpkin3".to_string()));
-    let e = commands.spawn().insert(GameLayer::Plants)
-     .insert(Plant(60))
-     .insert(RoundsTillMature(10))
-     .insert(Health(10))
-     .insert(PrizePlant)
-     .insert(TilePos(IVec2::new(5,6)))
-     .id();
-    to_spawn.push((e, "big_pumpkin4".to_string()));
+    for (i, entry) in scenario.prize_entries.iter().enumerate() {
+        for (pos, sprite) in &entry.tiles {
+            let e = commands.spawn().insert(GameLayer::Plants)
+             .insert(Plant(60))
+             .insert(RoundsTillMature(entry.rounds_till_mature))
+             .insert(Health(entry.health_per_tile))
+             .insert(PrizePlant(i))
+             .insert(TilePos(*pos))
+             .id();
+            to_spawn.push((e, sprite.to_string()));
+        }
+    }
 
     Ok(to_spawn)
 }
@@ -1,28 +1,233 @@
-use crate::loading::FontAssets;
-use crate::GameState;
+use crate::{
+    GameState,
+    rng::GameSeed,
+    scenario::{Difficulty, Scenario},
+    save_game::{continue_saved_game, LoadedGame, SavedGameAvailable},
+    transitions::Transitions,
+};
 use bevy::prelude::*;
-use bevy_egui::{egui, EguiContext, EguiPlugin};
+use bevy_egui::{egui, EguiContext};
 
 pub struct MenuPlugin;
 
+/// Every page of the menu is its own `GameState`. Pages are pushed on top of
+/// `GameState::Menu` so "Back" is always a pop.
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_system_set(
             SystemSet::on_update(GameState::Menu)
                 .with_system(menu_ui.system())
         );
+        app.add_system_set(
+            SystemSet::on_update(GameState::NewGame)
+                .with_system(new_game_ui.system())
+        );
+        app.add_system_set(
+            SystemSet::on_update(GameState::Credits)
+                .with_system(credits_ui.system())
+        );
+    }
+}
+
+const CREDITS: &str = include_str!("../../credits/CREDITS.md");
+
+/// Draws a column of buttons that can be driven by the mouse or by the
+/// keyboard (Up/Down to move, Enter/Space to activate). Returns the index of
+/// the button that was activated this frame. Keys it acts on are consumed so
+/// a page opened by this press doesn't see it as well.
+pub fn button_list(
+    ui: &mut egui::Ui,
+    keyboard_input: &mut Input<KeyCode>,
+    selected: &mut usize,
+    buttons: &[(&str, bool)],
+) -> Option<usize> {
+    let enabled: Vec<usize> = buttons.iter().enumerate().filter(|(_, (_, e))| *e).map(|(i, _)| i).collect();
+    if enabled.is_empty() {
+        return None;
     }
+    let mut position = enabled.iter().position(|i| i == selected).unwrap_or(0);
+    let mut moved = false;
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        position = (position + 1) % enabled.len();
+        moved = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        position = (position + enabled.len() - 1) % enabled.len();
+        moved = true;
+    }
+    *selected = enabled[position];
+    // egui already activates a focused button on Enter/Space, and a focused
+    // text field needs those keys for itself.
+    let activate = !ui.ctx().wants_keyboard_input()
+        && (keyboard_input.just_pressed(KeyCode::Return) || keyboard_input.just_pressed(KeyCode::Space));
+
+    let mut clicked = None;
+    for (i, (label, is_enabled)) in buttons.iter().enumerate() {
+        ui.scope(|ui| {
+            ui.set_enabled(*is_enabled);
+            let response = ui.button(*label);
+            if moved && i == *selected {
+                response.request_focus();
+            }
+            if response.clicked() || (activate && i == *selected) {
+                clicked = Some(i);
+            }
+        });
+    }
+    if clicked.is_some() {
+        keyboard_input.reset(KeyCode::Return);
+        keyboard_input.reset(KeyCode::Space);
+    }
+    clicked
 }
 
 fn menu_ui(
     egui_context: Res<EguiContext>,
-    mut state: ResMut<State<GameState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut transitions: ResMut<Transitions>,
+    mut selected: Local<usize>,
+    mut saved_game_available: ResMut<SavedGameAvailable>,
+    mut loaded_game: ResMut<LoadedGame>,
+    mut scenario: ResMut<Scenario>,
+    mut difficulty: ResMut<Difficulty>,
+    mut seed: ResMut<GameSeed>,
+    #[cfg(not(target_arch = "wasm32"))]
+    mut app_exit_events: EventWriter<bevy::app::AppExit>,
+) {
+    let mut buttons = vec![
+        ("New Game", true),
+        ("Continue", saved_game_available.0),
+        ("Settings", true),
+        ("High Scores", true),
+        ("Credits", true),
+    ];
+    #[cfg(not(target_arch = "wasm32"))]
+    buttons.push(("Quit", true));
+
+    egui::CentralPanel::default().show(egui_context.ctx(), |ui| {
+        ui.vertical_centered(|ui| {
+            ui.heading("Rabbit Garden");
+            ui.add_space(20.0);
+            match button_list(ui, &mut keyboard_input, &mut selected, &buttons) {
+                Some(0) => { transitions.push_game(GameState::NewGame); }
+                Some(1) => match continue_saved_game(&mut loaded_game, &mut scenario, &mut difficulty, &mut seed) {
+                    Ok(()) => { transitions.set_game(GameState::Playing); }
+                    Err(e) => {
+                        warn!("Could not load the saved game: {}", e);
+                        saved_game_available.0 = false;
+                    }
+                },
+                Some(2) => { transitions.push_game(GameState::Settings); }
+                Some(3) => { transitions.push_game(GameState::HighScores); }
+                Some(4) => { transitions.push_game(GameState::Credits); }
+                #[cfg(not(target_arch = "wasm32"))]
+                Some(5) => app_exit_events.send(bevy::app::AppExit),
+                _ => (),
+            }
+        })
+    });
+}
+
+fn new_game_ui(
+    egui_context: Res<EguiContext>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut transitions: ResMut<Transitions>,
+    mut scenario: ResMut<Scenario>,
+    mut difficulty: ResMut<Difficulty>,
+    mut seed: ResMut<GameSeed>,
+    mut seed_text: Local<String>,
+    mut selected: Local<usize>,
 ) {
-        egui::CentralPanel::default().show(egui_context.ctx(), |ui| {
-            ui.centered_and_justified(|ui| {
-                if ui.button("Play").clicked() {
-                    state.set(GameState::Playing);
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        transitions.pop_game();
+        return;
+    }
+    egui::CentralPanel::default().show(egui_context.ctx(), |ui| {
+        ui.vertical_centered(|ui| {
+            ui.heading("New Game");
+            ui.add_space(20.0);
+
+            ui.label("Scenario");
+            ui.horizontal(|ui| {
+                for option in Scenario::all() {
+                    let name = option.name;
+                    ui.selectable_value(&mut *scenario, option, name);
+                }
+            });
+            ui.label("Difficulty");
+            ui.horizontal(|ui| {
+                for option in Difficulty::all().iter() {
+                    ui.selectable_value(&mut *difficulty, *option, option.name());
+                }
+            });
+            let rules = scenario.queue_rules_for(*difficulty);
+            let on_off = |on| if on { "on" } else { "off" };
+            ui.label(format!("Hold slot: {}, pick any tile: {}", on_off(rules.hold_slot), on_off(rules.pick_any)));
+            ui.horizontal(|ui| {
+                ui.label("Seed (blank for random)");
+                ui.text_edit_singleline(&mut *seed_text);
+            });
+            ui.add_space(20.0);
+
+            match button_list(ui, &mut keyboard_input, &mut selected, &[("Start", true), ("Back", true)]) {
+                Some(0) => {
+                    *seed = seed_text.trim().parse().map(GameSeed).unwrap_or_else(|_| GameSeed::random());
+                    transitions.replace_game(GameState::Playing);
                 }
-            })
+                Some(1) => { transitions.pop_game(); }
+                _ => (),
+            }
+        })
+    });
+}
+
+fn credits_ui(
+    egui_context: Res<EguiContext>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut transitions: ResMut<Transitions>,
+    mut selected: Local<usize>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        transitions.pop_game();
+        return;
+    }
+    egui::CentralPanel::default().show(egui_context.ctx(), |ui| {
+        for line in CREDITS.lines() {
+            if let Some(heading) = line.strip_prefix('#') {
+                ui.heading(heading.trim_start_matches('#').trim());
+            } else if !line.trim().is_empty() {
+                ui.label(strip_markdown_links(line.trim_start_matches("* ")));
+            }
+        }
+        ui.add_space(20.0);
+        if button_list(ui, &mut keyboard_input, &mut selected, &[("Back", true)]).is_some() {
+            transitions.pop_game();
+        }
+    });
+}
+
+/// Turns `[text](url)` into `text (url)` so the credits read fine as plain text.
+fn strip_markdown_links(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find('[') {
+        let link = rest[start..].find("](").and_then(|mid| {
+            rest[start + mid..].find(')').map(|end| (mid, start + mid + end))
         });
+        match link {
+            Some((mid, end)) => {
+                out.push_str(&rest[..start]);
+                out.push_str(&rest[start + 1..start + mid]);
+                out.push_str(" (");
+                out.push_str(&rest[start + mid + 2..end]);
+                out.push(')');
+                rest = &rest[end + 1..];
+            }
+            None => break,
+        }
+    }
+    out.push_str(rest);
+    out
 }
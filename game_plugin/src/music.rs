@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+use game_music::{MusicControl, MusicParam, MusicTracks, SynthPatch};
+use crate::{
+    GameState,
+    loading::AudioAssets,
+    pests::{IdlePest, Pest},
+    plants::{PrizePlant, RoundsTillMature},
+};
+
+/// Pests on the board for the music to be at full intensity.
+const FULL_INTENSITY_PESTS: f32 = 8.0;
+
+/// Picks the track for the game's state and keeps the music's parameters
+/// in step with the game.
+pub struct MusicDirectorPlugin;
+
+impl Plugin for MusicDirectorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(direct_music.system());
+        app.add_system(select_track.system());
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_startup_system(watch_patches.system());
+    }
+}
+
+/// Reload the `.synth` patches when they're edited, so music can be worked
+/// on while the game runs.
+#[cfg(not(target_arch = "wasm32"))]
+fn watch_patches(asset_server: Res<AssetServer>) {
+    if let Err(e) = asset_server.watch_for_changes() {
+        warn!("Not watching assets for changes: {:?}", e);
+    }
+}
+
+/// The track that goes with a state. Pages pushed over play keep the game's
+/// music going.
+fn track_for<'a>(state: &State<GameState>, audio: &'a AudioAssets) -> (&'static str, &'a Handle<SynthPatch>) {
+    match state.current() {
+        GameState::Playing | GameState::Paused => ("playing", &audio.playing_music),
+        GameState::PrizePlantScoring => ("scoring", &audio.scoring_music),
+        _ if state.inactives().contains(&GameState::Playing) => ("playing", &audio.playing_music),
+        _ => ("menu", &audio.menu_music),
+    }
+}
+
+fn select_track(
+    tracks: Res<MusicTracks>,
+    state: Res<State<GameState>>,
+    audio: Option<Res<AudioAssets>>,
+    patches: Res<Assets<SynthPatch>>,
+    mut patch_events: EventReader<AssetEvent<SynthPatch>>,
+    mut playing: Local<Option<Handle<SynthPatch>>>,
+) {
+    tracks.collect_retired();
+    let audio = match audio {
+        Some(audio) => audio,
+        None => return,
+    };
+    let (name, handle) = track_for(&state, &audio);
+    let edited = patch_events.iter().any(|event| match event {
+        AssetEvent::Modified { handle: modified } => Some(modified) == playing.as_ref(),
+        _ => false,
+    });
+    if playing.as_ref() == Some(handle) && !edited {
+        return;
+    }
+    if let Some(patch) = patches.get(handle) {
+        if let Err(e) = tracks.play(name, &patch.source) {
+            warn!("Could not play the {} music: {}", name, e);
+        } else if edited {
+            info!("Reloaded the {} music", name);
+        }
+        *playing = Some(handle.clone());
+    }
+}
+
+fn direct_music(
+    control: Res<MusicControl>,
+    state: Res<State<GameState>>,
+    pest_query: Query<Entity, (With<Pest>, Without<IdlePest>)>,
+    prize_query: Query<&RoundsTillMature, With<PrizePlant>>,
+) {
+    let (intensity, brightness, scoring) = match state.current() {
+        GameState::Playing | GameState::Paused => {
+            let pests = pest_query.iter().count() as f32;
+            // The filter opens as the nearest prize entry comes up to judging
+            let brightness = prize_query.iter()
+                .map(|rounds| rounds.0.max(0))
+                .min()
+                .map_or(1.0, |rounds| 1.0 / (1.0 + rounds as f32));
+            (pests / FULL_INTENSITY_PESTS, brightness, 0.0)
+        }
+        GameState::PrizePlantScoring => (0.0, 1.0, 1.0),
+        _ => (0.0, 1.0, 0.0),
+    };
+    control.set(MusicParam::Intensity, intensity);
+    control.set(MusicParam::Brightness, brightness);
+    control.set(MusicParam::Scoring, scoring);
+}
@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use crate::{
+    GameState,
+    menu::button_list,
+    save_game::SaveOnExit,
+    transitions::Transitions,
+};
+
+pub struct PausePlugin;
+
+/// Pausing pushes `GameState::Paused` on top of `GameState::Playing`, so
+/// everything gated on `Playing` (including `TurnClock`) simply stops running
+/// and the board stays intact underneath. The player's turn keeps going in
+/// `TurnState`, but its input systems also wait for `Playing`, so keys meant
+/// for the pause menu never place or rotate a tile.
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PauseConfirmation>();
+        app.add_system(toggle_pause.system());
+        app.add_system_set(
+            SystemSet::on_update(GameState::Paused)
+                .with_system(pause_ui.system())
+        );
+    }
+}
+
+/// Actions that throw the current run away and need to be confirmed first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PendingConfirmation {
+    Restart,
+}
+
+#[derive(Default)]
+struct PauseConfirmation(Option<PendingConfirmation>);
+
+fn toggle_pause(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut transitions: ResMut<Transitions>,
+    mut confirmation: ResMut<PauseConfirmation>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        match state.current() {
+            GameState::Playing => {
+                keyboard_input.reset(KeyCode::Escape);
+                transitions.push_game(GameState::Paused);
+            }
+            GameState::Paused => {
+                keyboard_input.reset(KeyCode::Escape);
+                // Escape backs out of a confirmation before it resumes the game
+                if confirmation.0.take().is_none() {
+                    transitions.pop_game();
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn pause_ui(
+    egui_context: Res<EguiContext>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut transitions: ResMut<Transitions>,
+    mut selected: Local<usize>,
+    mut confirmation: ResMut<PauseConfirmation>,
+    mut save_on_exit: ResMut<SaveOnExit>,
+) {
+    egui::Window::new("Paused")
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_context.ctx(), |ui| {
+            if let Some(pending) = confirmation.0 {
+                let question = match pending {
+                    PendingConfirmation::Restart => "Restart? This run will be lost.",
+                };
+                ui.label(question);
+                match button_list(ui, &mut keyboard_input, &mut selected, &[("Yes", true), ("No", true)]) {
+                    Some(0) => {
+                        confirmation.0 = None;
+                        match pending {
+                            PendingConfirmation::Restart => { transitions.replace_game(GameState::Playing); }
+                        }
+                    }
+                    Some(_) => confirmation.0 = None,
+                    None => (),
+                }
+                return;
+            }
+            let buttons = [("Resume", true), ("Restart", true), ("Settings", true), ("Save and quit", true)];
+            match button_list(ui, &mut keyboard_input, &mut selected, &buttons) {
+                Some(0) => { transitions.pop_game(); }
+                Some(1) => {
+                    confirmation.0 = Some(PendingConfirmation::Restart);
+                    // Default to "No"
+                    *selected = 1;
+                }
+                Some(2) => { transitions.push_game(GameState::Settings); }
+                Some(3) => {
+                    save_on_exit.0 = true;
+                    transitions.replace_game(GameState::Menu);
+                }
+                _ => (),
+            }
+        });
+}
@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+// Small key/value store used for anything that needs to outlive a session
+// (high scores, settings, saved games).
+//
+// On native every key becomes a `.ron` file in the platform data directory,
+// on wasm it becomes an entry in the browser's `localStorage`.
+const APP_NAME: &str = "rabbit_garden";
+
+pub fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let source = read(key)?;
+    match ron::de::from_str(&source) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            bevy::log::warn!("Could not parse stored {}: {}", key, e);
+            None
+        }
+    }
+}
+
+pub fn save<T: Serialize>(key: &str, value: &T) -> Result<()> {
+    let source = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?;
+    write(key, &source)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn path_for(key: &str) -> Result<std::path::PathBuf> {
+    let dir = dirs::data_dir()
+        .ok_or_else(|| anyhow!("No data directory on this platform"))?
+        .join(APP_NAME);
+    Ok(dir.join(format!("{}.ron", key)))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(key: &str) -> Option<String> {
+    std::fs::read_to_string(path_for(key).ok()?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write(key: &str, source: &str) -> Result<()> {
+    let path = path_for(key)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, source)?;
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn remove(key: &str) -> Result<()> {
+    let path = path_for(key)?;
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Result<web_sys::Storage> {
+    web_sys::window()
+        .ok_or_else(|| anyhow!("No window"))?
+        .local_storage()
+        .map_err(|_| anyhow!("localStorage is not accessible"))?
+        .ok_or_else(|| anyhow!("localStorage is not available"))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn storage_key(key: &str) -> String {
+    format!("{}/{}", APP_NAME, key)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read(key: &str) -> Option<String> {
+    local_storage().ok()?.get_item(&storage_key(key)).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write(key: &str, source: &str) -> Result<()> {
+    local_storage()?
+        .set_item(&storage_key(key), source)
+        .map_err(|_| anyhow!("Could not write {} to localStorage", key))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn remove(key: &str) -> Result<()> {
+    local_storage()?
+        .remove_item(&storage_key(key))
+        .map_err(|_| anyhow!("Could not remove {} from localStorage", key))
+}
+
+/// Seconds since the unix epoch, which `SystemTime` can't give us in the browser.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn now() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
@@ -1,16 +1,23 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use bevy::prelude::*;
 use anyhow::Result;
 use crate::{
+    animation::{Animated, TweenStyle},
+    events::GameEvent,
     GameState,
-    turn_structure::TurnState,
+    turn_structure::{RoundEndReason, RoundOver, TurnState},
     loading::TextureAssets,
     plants::Health,
     map::{GameLayer, MAP_SIZE, TilePos, Blocking},
+    rng::GameRng,
+    scoring::RunStats,
+    scenario::Difficulty,
+    main_ui::spawn_tile_sprites,
 };
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Pest {
     pattern: Vec<IVec2>,
     move_idx: usize,
@@ -21,9 +28,14 @@ pub struct Pest {
     stop_after_consumption: bool,
 }
 impl Pest {
-    fn spawn(self, position: TilePos, commands: &mut Commands) -> Entity {
+    pub fn spawn(self, position: TilePos, commands: &mut Commands) -> Entity {
         let is_blocking = self.is_blocking;
+        let style = match self.sprite.as_str() {
+            "wind" => TweenStyle::Gust,
+            _ => TweenStyle::Hop,
+        };
         let e = commands.spawn()
+                .insert(Animated(style))
                 .insert(self)
                 .insert(IdlePest)
                 .insert(position)
@@ -35,6 +47,10 @@ impl Pest {
         e
     }
 
+    pub fn sprite(&self) -> &str {
+        &self.sprite
+    }
+
     fn rightward_rabbit() -> Self {
         Self {
             pattern: vec![
@@ -157,10 +173,35 @@ impl Pest {
 }
 pub struct IdlePest;
 
+/// Longest a pest phase may run before the round is called.
+const MAX_ROUND_TICKS: u32 = 100;
+
+/// A pest gives up and leaves after being stuck for this many ticks in a row.
+const PEST_PATIENCE: usize = 3;
+
+/// Bookkeeping for the current round's pest phase.
+#[derive(Default)]
+struct PestPhase {
+    ticks: u32,
+    /// Pest positions and pattern steps seen since the board last changed.
+    /// Seeing one again means the pests are looping.
+    seen: HashSet<Vec<(IVec2, usize)>>,
+}
+
+/// What happened during one tick of pest movement.
+struct PestTick {
+    /// Pests that left the board or were used up this tick. Their despawns
+    /// haven't been applied yet.
+    gone: HashSet<Entity>,
+    /// Whether anything on the board was eaten or damaged
+    board_changed: bool,
+}
+
 pub struct PestPlugin;
 
 impl Plugin for PestPlugin {
     fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PestPhase>();
         app.add_system_set(
             SystemSet::on_exit(TurnState::RoundSetup)
                 .with_system(move_idle_pests_in.system().label("move idle"))
@@ -172,8 +213,11 @@ impl Plugin for PestPlugin {
         );
         app.add_system_set(
             SystemSet::on_enter(TurnState::PestTurnA)
-                .with_system(pest_movement.system())
-                .with_system(maybe_end_pest_turn.system())
+                .with_system(pest_movement.system().chain(resolve_pest_tick.system()))
+        );
+        app.add_system_set(
+            SystemSet::on_enter(TurnState::PlayerTurn)
+                .with_system(reset_pest_phase.system())
         );
     }
 }
@@ -185,12 +229,26 @@ fn pest_movement(
         Query<(Entity, &mut Pest, &mut TilePos, &GameLayer), Without<IdlePest>>
     )>,
     mut health_query: Query<&mut Health>,
-) {
+    mut stats: ResMut<RunStats>,
+    mut events: EventWriter<GameEvent>,
+) -> PestTick {
+    let mut tick = PestTick {
+        gone: HashSet::new(),
+        board_changed: false,
+    };
     let mut current_positions = HashMap::with_capacity(10);
     for (e, pos, layer) in queries.q0().iter() {
         current_positions.insert(pos.0, (e, *layer));
     }
-    for (e, mut pest, mut pos, layer) in queries.q1_mut().iter_mut() {
+    // Move pests in board order rather than whatever order the ECS stores
+    // them in, which differs after a saved game is restored.
+    let mut order: Vec<(IVec2, Entity)> = queries.q1_mut().iter_mut().map(|(e, _, pos, _)| (pos.0, e)).collect();
+    order.sort_by_key(|(pos, e)| (pos.y, pos.x, *e));
+    for (_, e) in order {
+        let (e, mut pest, mut pos, layer) = match queries.q1_mut().get_mut(e) {
+            Ok(pest) => pest,
+            Err(_) => continue,
+        };
         let movement = pest.pattern[pest.move_idx];
         pest.move_idx = (pest.move_idx + 1) % pest.pattern.len();
         let new_pos = pos.0 + movement;
@@ -199,16 +257,29 @@ fn pest_movement(
             if let Some((other, layer)) = current_positions.get(&new_pos) {
                 if layer == &pest.consumption_layer {
                     did_move = true;
-                    if let Ok(mut health) = health_query.get_mut(*other) {
+                    tick.board_changed = true;
+                    let destroyed = if let Ok(mut health) = health_query.get_mut(*other) {
                         health.0 -= 1;
-                        if health.0 <= 0 {
-                            commands.entity(*other).despawn_recursive();
-                        }
+                        health.0 <= 0
                     } else {
+                        true
+                    };
+                    if destroyed {
                         commands.entity(*other).despawn_recursive();
+                        match layer {
+                            GameLayer::Plants => {
+                                stats.plants_lost += 1;
+                                events.send(GameEvent::PlantEaten { pos: new_pos });
+                            }
+                            GameLayer::Fences => events.send(GameEvent::FenceDestroyed { pos: new_pos }),
+                            GameLayer::Pests => (),
+                        }
+                    } else if *layer == GameLayer::Plants {
+                        events.send(GameEvent::PlantDamaged { entity: *other, pos: new_pos });
                     }
                     if pest.stop_after_consumption {
                         commands.entity(e).despawn_recursive();
+                        tick.gone.insert(e);
                     }
                 }
             } else {
@@ -217,30 +288,65 @@ fn pest_movement(
             if !did_move {
                 pest.ticks_since_move += 1;
             } else {
+                pest.ticks_since_move = 0;
                 current_positions.remove(&new_pos);
                 current_positions.insert(new_pos, (e, *layer));
                 *pos = TilePos(new_pos);
             }
         } else {
             commands.entity(e).despawn_recursive();
+            stats.pests_repelled += 1;
+            tick.gone.insert(e);
+            events.send(GameEvent::PestLeftBoard { pos: pos.0 });
         }
     }
+    tick
 }
 
-fn maybe_end_pest_turn(
-    mut state: ResMut<State<TurnState>>,
-    mut pest_query: Query<&Pest, Without<IdlePest>>,
+/// Sends away pests that have run out of patience and decides whether the
+/// round is over.
+fn resolve_pest_tick(
+    In(mut tick): In<PestTick>,
+    mut commands: Commands,
+    mut phase: ResMut<PestPhase>,
+    mut round_over: ResMut<RoundOver>,
+    mut stats: ResMut<RunStats>,
+    mut events: EventWriter<GameEvent>,
+    pest_query: Query<(Entity, &Pest, &TilePos), Without<IdlePest>>,
 ) {
-    for pest in pest_query.iter() {
-        if pest.ticks_since_move < 3 {
-            return
+    for (e, pest, pos) in pest_query.iter() {
+        if !tick.gone.contains(&e) && pest.ticks_since_move >= PEST_PATIENCE {
+            commands.entity(e).despawn_recursive();
+            stats.pests_repelled += 1;
+            tick.gone.insert(e);
+            events.send(GameEvent::PestLeftBoard { pos: pos.0 });
         }
     }
-    // If we've reached this point then every pest on the board
-    // has been unable to move for a full cycle of their motion
-    // pattern which means they are all permanently blocked and
-    // the round can end.
-    state.set(TurnState::EndOfRound);
+    let mut board: Vec<(IVec2, usize)> = pest_query.iter()
+        .filter(|(e, _, _)| !tick.gone.contains(e))
+        .map(|(_, pest, pos)| (pos.0, pest.move_idx))
+        .collect();
+    board.sort_by_key(|(pos, idx)| (pos.y, pos.x, *idx));
+
+    phase.ticks += 1;
+    if tick.board_changed {
+        phase.seen.clear();
+    }
+    round_over.0 = if board.is_empty() {
+        Some(RoundEndReason::PestsGone)
+    } else if !phase.seen.insert(board) {
+        Some(RoundEndReason::Stalemate)
+    } else if phase.ticks >= MAX_ROUND_TICKS {
+        Some(RoundEndReason::OutOfTime)
+    } else {
+        None
+    };
+}
+
+fn reset_pest_phase(
+    mut phase: ResMut<PestPhase>,
+) {
+    *phase = PestPhase::default();
 }
 
 fn move_idle_pests_in(
@@ -263,8 +369,12 @@ fn move_idle_pests_in(
 
 fn spawn_pests(
     mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    difficulty: Res<Difficulty>,
+    mut events: EventWriter<GameEvent>,
 ) -> Result<Vec<(Entity, String)>> {
-    let mut rng = thread_rng();
+    let rng = &mut rng.0;
+    let wind_chance = difficulty.wind_chance();
     let mut spawn_slots = vec![];
     for i in 3..MAP_SIZE-2 {
         spawn_slots.push((0, i));
@@ -272,30 +382,30 @@ fn spawn_pests(
         spawn_slots.push((i, 0));
         spawn_slots.push((i, MAP_SIZE-1));
     }
-    let count = rng.gen_range(1..4);
+    let count = rng.gen_range(difficulty.pest_count());
     let mut spawned = Vec::with_capacity(count);
-    for (x, y) in spawn_slots.choose_multiple(&mut rng, count) {
+    for (x, y) in spawn_slots.choose_multiple(rng, count) {
         let mut position = TilePos(IVec2::new(*x,*y));
         let pest = if *x == 0 {
-            if rng.gen::<f32>() > 0.1 {
+            if rng.gen::<f32>() > wind_chance {
                 Pest::rightward_rabbit()
             } else {
                 Pest::rightward_wind()
             }
         } else if *x == MAP_SIZE-1 {
-            if rng.gen::<f32>() > 0.1 {
+            if rng.gen::<f32>() > wind_chance {
                 Pest::leftward_rabbit()
             } else {
                 Pest::leftward_wind()
             }
         } else if *y == 0 {
-            if rng.gen::<f32>() > 0.1 {
+            if rng.gen::<f32>() > wind_chance {
                 Pest::upward_rabbit()
             } else {
                 Pest::upward_wind()
             }
         } else {
-            if rng.gen::<f32>() > 0.1 {
+            if rng.gen::<f32>() > wind_chance {
                 Pest::downward_rabbit()
             } else {
                 Pest::downward_wind()
@@ -303,7 +413,10 @@ fn spawn_pests(
         };
         //FIXME: This is dumb and tangled from too much fiddling
         let sprite = pest.sprite.clone();
-        spawned.push((pest.spawn(position, &mut commands), sprite));
+        let wind = sprite == "wind";
+        let e = pest.spawn(position, &mut commands);
+        events.send(GameEvent::PestSpawned { entity: e, pos: position.0, wind });
+        spawned.push((e, sprite));
     }
     Ok(spawned)
 }
@@ -316,26 +429,3 @@ fn despawn_pests(
         commands.entity(e).despawn_recursive();
     }
 }
-
-fn spawn_tile_sprites(
-    In(to_spawn): In<Result<Vec<(Entity, String)>>>,
-    mut commands: Commands,
-    textures: Res<TextureAssets>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-) {
-    if let Ok(to_spawn) = to_spawn {
-        for (e, sprite) in &to_spawn {
-            let handle = match sprite.as_str() {
-                "rabbit" => textures.rabbit.clone(),
-                "wind" => textures.wind.clone(),
-                _ => unimplemented!()
-            };
-            commands.entity(*e).insert_bundle(SpriteBundle {
-                material: materials.add(handle.into()),
-                transform: Transform::from_xyz(0.0, 0.0, 1.0),
-                ..Default::default()
-            });
-        }
-    }
-}
-
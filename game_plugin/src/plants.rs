@@ -1,15 +1,17 @@
 use bevy::prelude::*;
 use crate::{
-    GameState,
     turn_structure::TurnState,
-    scoring::PrizePlantScore,
-    map::{GameLayer, TilePos}
+    scoring::RunStats,
+    map::TilePos,
+    events::GameEvent,
 };
 
 pub struct RoundsTillMature(pub i32);
 pub struct Plant(pub u32);
 pub struct Health(pub i32);
-pub struct PrizePlant;
+/// Part of a prize entry, identified by its index in the scenario's
+/// `prize_entries`. Prize plants are judged rather than harvested.
+pub struct PrizePlant(pub usize);
 
 pub struct PlantPlugin;
 impl Plugin for PlantPlugin {
@@ -17,7 +19,6 @@ impl Plugin for PlantPlugin {
         app.add_system_set(
             SystemSet::on_enter(TurnState::EndOfRound)
                 .with_system(increment_maturity.system().label("incr_maturity"))
-                .with_system(fail_out_to_scoring.system())
         );
         app.add_system_set(
             SystemSet::on_enter(TurnState::RoundCleanup)
@@ -34,28 +35,17 @@ fn increment_maturity(
     }
 }
 
-fn fail_out_to_scoring(
-    mut state: ResMut<State<GameState>>,
-    plant_query: Query<&PrizePlant>,
-) {
-    if plant_query.iter().count() <= 0 {
-        state.set(GameState::PrizePlantScoring);
-    }
-}
-
 fn despawn_mature_plants(
     mut commands: Commands,
-    plant_query: Query<(Entity, &RoundsTillMature, Option<&PrizePlant>, &Health), With<Plant>>,
-    mut state: ResMut<State<GameState>>,
-    mut prize_plant_score: ResMut<PrizePlantScore>,
+    plant_query: Query<(Entity, &Plant, &TilePos, &RoundsTillMature), Without<PrizePlant>>,
+    mut stats: ResMut<RunStats>,
+    mut events: EventWriter<GameEvent>,
 ) {
-    for (e, rounds_till_mature, maybe_prize_plant, health) in plant_query.iter() {
+    for (e, plant, pos, rounds_till_mature) in plant_query.iter() {
         if rounds_till_mature.0 <= 0 {
             commands.entity(e).despawn_recursive();
-            if maybe_prize_plant.is_some() {
-                prize_plant_score.0 = health.0 as u32;
-                state.set(GameState::PrizePlantScoring);
-            }
+            stats.plants_harvested += 1;
+            events.send(GameEvent::PlantMatured { pos: pos.0, value: plant.0 });
         }
     }
 }
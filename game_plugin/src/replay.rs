@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{
+    persistence,
+    rng::GameSeed,
+    scenario::{Difficulty, Scenario},
+    save_game::new_run,
+};
+
+/// Everything else in a run follows from the seed, so a replay only needs
+/// the seed, the scenario, the difficulty and what the player did.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub scenario: String,
+    /// Replays from before difficulties were recorded were all `Normal`
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    pub actions: Vec<ReplayAction>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ReplayAction {
+    Place(IVec2),
+    /// Another queued tile was picked to be placed next
+    Select(usize),
+    /// The current tile was swapped with the hold slot
+    Hold,
+    /// The next placement was turned to this many quarter turns
+    Rotate(u8),
+}
+
+/// The replay of the run in progress (or the one that just finished).
+#[derive(Default)]
+pub struct ReplayRecorder(pub Replay);
+
+impl ReplayRecorder {
+    pub fn record(&mut self, action: ReplayAction) {
+        self.0.actions.push(action);
+    }
+
+    /// Stores the replay under a key derived from the current time and
+    /// returns that key.
+    pub fn save(&self) -> anyhow::Result<String> {
+        let key = format!("replays/{}-{}", persistence::now(), self.0.seed);
+        persistence::save(&key, &self.0)?;
+        Ok(key)
+    }
+}
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ReplayRecorder>();
+        app.add_system_set(
+            new_run()
+                .with_system(start_recording.system())
+        );
+    }
+}
+
+fn start_recording(
+    seed: Res<GameSeed>,
+    scenario: Res<Scenario>,
+    difficulty: Res<Difficulty>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    recorder.0 = Replay {
+        seed: seed.0,
+        scenario: scenario.id.to_string(),
+        difficulty: *difficulty,
+        actions: vec![],
+    };
+}
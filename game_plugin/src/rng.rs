@@ -0,0 +1,36 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use crate::save_game::new_run;
+
+/// The seed of the current run. All of the run's randomness is drawn from
+/// [GameRng] so replaying a seed reproduces the same queue and pests.
+pub struct GameSeed(pub u64);
+
+impl GameSeed {
+    pub fn random() -> Self {
+        GameSeed(thread_rng().gen())
+    }
+}
+
+pub struct GameRng(pub ChaCha8Rng);
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(GameSeed::random())
+           .insert_resource(GameRng(ChaCha8Rng::seed_from_u64(0)));
+        app.add_system_set(
+            new_run()
+                .with_system(reseed_rng.system().label("reseed rng"))
+        );
+    }
+}
+
+fn reseed_rng(
+    seed: Res<GameSeed>,
+    mut rng: ResMut<GameRng>,
+) {
+    rng.0 = ChaCha8Rng::seed_from_u64(seed.0);
+}
@@ -0,0 +1,502 @@
+use anyhow::{anyhow, Result};
+use bevy::{prelude::*, ecs::schedule::ShouldRun};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use crate::{
+    GameState,
+    persistence,
+    loading::TextureAssets,
+    map::{Fence, GameLayer, TilePos},
+    pests::{IdlePest, Pest},
+    plants::{Health, Plant, PrizePlant, RoundsTillMature},
+    main_ui::{spawn_tile_sprites, tile_texture, HeldTile, PlacableTile, PlacementRotation, SelectedPlacable, TileQueue, TileSprite},
+    judging::{PrizeJudgement, PrizeResults},
+    scoring::RunStats,
+    replay::{Replay, ReplayRecorder},
+    rng::{GameRng, GameSeed},
+    scenario::{Difficulty, Scenario},
+    turn_structure::{ResumeTurn, TurnState},
+};
+
+const SAVE_KEY: &str = "savegame";
+
+/// Bump this whenever `SaveGame` changes shape and add a migration from the
+/// previous version to `parse_save`.
+const SAVE_VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub scenario: String,
+    pub difficulty: Difficulty,
+    pub seed: u64,
+    pub rng: ChaCha8Rng,
+    pub turn_state: TurnState,
+    pub tiles: Vec<SavedTile>,
+    pub queue: Vec<PlacableTile>,
+    pub selected: usize,
+    pub held: Option<PlacableTile>,
+    pub held_swapped_this_turn: bool,
+    /// Quarter turns of the next placement
+    pub rotation: u8,
+    /// Score of each prize entry that has already been judged
+    pub prize_scores: Vec<Option<u32>>,
+    pub stats: RunStats,
+    pub replay: Replay,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedTile {
+    pub pos: IVec2,
+    pub layer: GameLayer,
+    pub sprite: String,
+    pub plant: Option<u32>,
+    pub health: Option<i32>,
+    pub rounds_till_mature: Option<i32>,
+    pub prize_plant: Option<usize>,
+    pub fence: bool,
+    pub pest: Option<Pest>,
+    pub idle_pest: bool,
+}
+
+/// The first save format, from before the hold slot.
+#[derive(Deserialize)]
+struct SaveGameV1 {
+    scenario: String,
+    difficulty: Difficulty,
+    seed: u64,
+    rng: ChaCha8Rng,
+    turn_state: TurnState,
+    tiles: Vec<SavedTile>,
+    queue: Vec<PlacableTile>,
+    prize_scores: Vec<Option<u32>>,
+    stats: RunStats,
+    replay: Replay,
+}
+
+impl From<SaveGameV1> for SaveGameV2 {
+    fn from(v1: SaveGameV1) -> Self {
+        SaveGameV2 {
+            scenario: v1.scenario,
+            difficulty: v1.difficulty,
+            seed: v1.seed,
+            rng: v1.rng,
+            turn_state: v1.turn_state,
+            tiles: v1.tiles,
+            queue: v1.queue,
+            selected: 0,
+            held: None,
+            held_swapped_this_turn: false,
+            prize_scores: v1.prize_scores,
+            stats: v1.stats,
+            replay: v1.replay,
+        }
+    }
+}
+
+/// The second save format, from before the placement rotation.
+#[derive(Deserialize)]
+struct SaveGameV2 {
+    scenario: String,
+    difficulty: Difficulty,
+    seed: u64,
+    rng: ChaCha8Rng,
+    turn_state: TurnState,
+    tiles: Vec<SavedTile>,
+    queue: Vec<PlacableTile>,
+    selected: usize,
+    held: Option<PlacableTile>,
+    held_swapped_this_turn: bool,
+    prize_scores: Vec<Option<u32>>,
+    stats: RunStats,
+    replay: Replay,
+}
+
+impl From<SaveGameV2> for SaveGame {
+    fn from(v2: SaveGameV2) -> Self {
+        SaveGame {
+            version: 3,
+            scenario: v2.scenario,
+            difficulty: v2.difficulty,
+            seed: v2.seed,
+            rng: v2.rng,
+            turn_state: v2.turn_state,
+            tiles: v2.tiles,
+            queue: v2.queue,
+            selected: v2.selected,
+            held: v2.held,
+            held_swapped_this_turn: v2.held_swapped_this_turn,
+            rotation: 0,
+            prize_scores: v2.prize_scores,
+            stats: v2.stats,
+            replay: v2.replay,
+        }
+    }
+}
+
+/// Just enough of a save to find out which format it is in.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+fn parse_save(source: &str) -> Result<SaveGame> {
+    let header: SaveHeader = ron::de::from_str(source)?;
+    match header.version {
+        1 => Ok(SaveGameV2::from(ron::de::from_str::<SaveGameV1>(source)?).into()),
+        2 => Ok(ron::de::from_str::<SaveGameV2>(source)?.into()),
+        SAVE_VERSION => Ok(ron::de::from_str(source)?),
+        v => Err(anyhow!("Unsupported save version {}", v)),
+    }
+}
+
+/// Reads the stored save, if there is one.
+pub fn load() -> Result<SaveGame> {
+    let source = persistence::read(SAVE_KEY).ok_or_else(|| anyhow!("No saved game"))?;
+    parse_save(&source)
+}
+
+/// A save waiting to be restored as `GameState::Playing` is entered. The
+/// `new_run` systems don't run while this is set.
+#[derive(Default)]
+pub struct LoadedGame(pub Option<SaveGame>);
+
+/// Whether "Continue" has anything to continue.
+pub struct SavedGameAvailable(pub bool);
+
+/// Set by "Save and quit" so the run is written out as `GameState::Playing`
+/// is left.
+#[derive(Default)]
+pub struct SaveOnExit(pub bool);
+
+/// Set when a restore resumes at `TurnState::StartOfRound`, so its autosave
+/// doesn't rewrite the save that was just loaded.
+#[derive(Default)]
+struct SkipAutosave(bool);
+
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<LoadedGame>();
+        app.init_resource::<SaveOnExit>();
+        app.init_resource::<SkipAutosave>();
+        app.insert_resource(SavedGameAvailable(persistence::read(SAVE_KEY).is_some()));
+        app.add_system_set(
+            enter_playing()
+                .with_system(restore_game.system().chain(spawn_tile_sprites.system()))
+        );
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(clear_loaded_game.system())
+        );
+        app.add_system_set(
+            SystemSet::on_exit(GameState::Playing)
+                .with_system(save_on_exit.exclusive_system())
+        );
+        app.add_system_set(
+            SystemSet::on_enter(GameState::PrizePlantScoring)
+                .with_system(delete_finished_save.system())
+        );
+        // The browser may close the tab at any time, but writing to
+        // localStorage every round is slow enough to notice, so wasm only
+        // saves on "Save and quit".
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_system_set(
+            SystemSet::on_enter(TurnState::StartOfRound)
+                .with_system(autosave.exclusive_system())
+        );
+    }
+}
+
+/// Entering `GameState::Playing`. Only one set may use this, it labels the
+/// criteria `new_run` pipes from.
+fn enter_playing() -> SystemSet {
+    SystemSet::new().with_run_criteria(State::on_enter(GameState::Playing).label("enter playing"))
+}
+
+/// Systems that set up a fresh run as `GameState::Playing` is entered. They
+/// are skipped when a saved game is restored instead.
+pub fn new_run() -> SystemSet {
+    SystemSet::new().with_run_criteria(RunCriteria::pipe("enter playing", not_restoring.system()))
+}
+
+fn not_restoring(
+    In(entering): In<ShouldRun>,
+    loaded_game: Res<LoadedGame>,
+) -> ShouldRun {
+    if loaded_game.0.is_none() {
+        return entering;
+    }
+    match entering {
+        ShouldRun::YesAndCheckAgain | ShouldRun::NoAndCheckAgain => ShouldRun::NoAndCheckAgain,
+        ShouldRun::Yes | ShouldRun::No => ShouldRun::No,
+    }
+}
+
+fn snapshot(world: &mut World) -> SaveGame {
+    let mut tile_query = world.query::<(
+        &TilePos,
+        &GameLayer,
+        Option<&TileSprite>,
+        Option<&Plant>,
+        Option<&Health>,
+        Option<&RoundsTillMature>,
+        Option<&PrizePlant>,
+        Option<&Fence>,
+        Option<&Pest>,
+        Option<&IdlePest>,
+    )>();
+    let tiles = tile_query.iter(world).map(|(pos, layer, sprite, plant, health, rounds_till_mature, prize_plant, fence, pest, idle_pest)| {
+        SavedTile {
+            pos: pos.0,
+            layer: *layer,
+            sprite: sprite.map(|s| s.0.clone())
+                .or_else(|| pest.map(|p| p.sprite().to_string()))
+                .unwrap_or_default(),
+            plant: plant.map(|p| p.0),
+            health: health.map(|h| h.0),
+            rounds_till_mature: rounds_till_mature.map(|r| r.0),
+            prize_plant: prize_plant.map(|p| p.0),
+            fence: fence.is_some(),
+            pest: pest.cloned(),
+            idle_pest: idle_pest.is_some(),
+        }
+    }).collect();
+
+    let mut placable_query = world.query::<&PlacableTile>();
+    let queue = world.get_resource::<TileQueue>().unwrap().0.iter()
+        .filter_map(|e| placable_query.get(world, *e).ok().copied())
+        .collect();
+    let held_tile = world.get_resource::<HeldTile>().unwrap();
+    let held = held_tile.tile.and_then(|e| placable_query.get(world, e).ok().copied());
+    let held_swapped_this_turn = held_tile.swapped_this_turn;
+
+    SaveGame {
+        version: SAVE_VERSION,
+        scenario: world.get_resource::<Scenario>().unwrap().id.to_string(),
+        difficulty: *world.get_resource::<Difficulty>().unwrap(),
+        seed: world.get_resource::<GameSeed>().unwrap().0,
+        rng: world.get_resource::<GameRng>().unwrap().0.clone(),
+        turn_state: world.get_resource::<State<TurnState>>().unwrap().current().clone(),
+        tiles,
+        queue,
+        selected: world.get_resource::<SelectedPlacable>().unwrap().0,
+        held,
+        held_swapped_this_turn,
+        rotation: world.get_resource::<PlacementRotation>().unwrap().0,
+        prize_scores: world.get_resource::<PrizeResults>().unwrap().0.iter()
+            .map(|j| j.as_ref().map(|j| j.score))
+            .collect(),
+        stats: world.get_resource::<RunStats>().unwrap().clone(),
+        replay: world.get_resource::<ReplayRecorder>().unwrap().0.clone(),
+    }
+}
+
+fn write_save(world: &mut World) {
+    let save = snapshot(world);
+    match persistence::save(SAVE_KEY, &save) {
+        Ok(()) => world.get_resource_mut::<SavedGameAvailable>().unwrap().0 = true,
+        Err(e) => warn!("Could not save the game: {}", e),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn autosave(world: &mut World) {
+    let skip = std::mem::take(&mut world.get_resource_mut::<SkipAutosave>().unwrap().0);
+    if !skip {
+        write_save(world);
+    }
+}
+
+fn save_on_exit(world: &mut World) {
+    let requested = std::mem::take(&mut world.get_resource_mut::<SaveOnExit>().unwrap().0);
+    if requested {
+        write_save(world);
+    }
+}
+
+fn delete_finished_save(
+    mut available: ResMut<SavedGameAvailable>,
+) {
+    if let Err(e) = persistence::remove(SAVE_KEY) {
+        warn!("Could not remove the finished save: {}", e);
+    }
+    available.0 = false;
+}
+
+fn clear_loaded_game(
+    mut loaded_game: ResMut<LoadedGame>,
+) {
+    loaded_game.0 = None;
+}
+
+/// Turn state to continue from. Transitions whose `on_enter` work already
+/// happened before the save was written are skipped so it isn't done twice.
+fn resume_point(saved: &TurnState) -> TurnState {
+    match saved {
+        TurnState::Idle | TurnState::RoundSetup | TurnState::RoundCleanup => TurnState::Idle,
+        TurnState::StartOfRound => TurnState::StartOfRound,
+        TurnState::PlayerTurn => TurnState::PlayerTurn,
+        TurnState::PestTurnA | TurnState::PestTurnB => TurnState::PestTurnB,
+        TurnState::EndOfRound => TurnState::RoundCleanup,
+    }
+}
+
+fn restore_game(
+    mut commands: Commands,
+    loaded_game: Res<LoadedGame>,
+    scenario: Res<Scenario>,
+    textures: Res<TextureAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut queue: ResMut<TileQueue>,
+    mut held: ResMut<HeldTile>,
+    mut selected: ResMut<SelectedPlacable>,
+    mut rng: ResMut<GameRng>,
+    mut results: ResMut<PrizeResults>,
+    mut stats: ResMut<RunStats>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut rotation: ResMut<PlacementRotation>,
+    mut resume_turn: ResMut<ResumeTurn>,
+    mut skip_autosave: ResMut<SkipAutosave>,
+) -> Result<Vec<(Entity, String)>> {
+    let save = match &loaded_game.0 {
+        Some(save) => save,
+        None => return Ok(vec![]),
+    };
+
+    let mut to_spawn = Vec::with_capacity(save.tiles.len());
+    for tile in &save.tiles {
+        if tile_texture(&textures, &tile.sprite).is_none() {
+            warn!("Skipping saved tile at {:?} with unknown sprite {:?}", tile.pos, tile.sprite);
+            continue;
+        }
+        let e = if let Some(pest) = &tile.pest {
+            let e = pest.clone().spawn(TilePos(tile.pos), &mut commands);
+            if !tile.idle_pest {
+                commands.entity(e).remove::<IdlePest>();
+            }
+            e
+        } else {
+            commands.spawn().insert(TilePos(tile.pos)).insert(tile.layer).id()
+        };
+        let mut entity = commands.entity(e);
+        if let Some(plant) = tile.plant {
+            entity.insert(Plant(plant));
+        }
+        if let Some(health) = tile.health {
+            entity.insert(Health(health));
+        }
+        if let Some(rounds_till_mature) = tile.rounds_till_mature {
+            entity.insert(RoundsTillMature(rounds_till_mature));
+        }
+        if let Some(prize_plant) = tile.prize_plant {
+            entity.insert(PrizePlant(prize_plant));
+        }
+        if tile.fence {
+            entity.insert(Fence);
+        }
+        to_spawn.push((e, tile.sprite.clone()));
+    }
+
+    queue.0 = save.queue.iter()
+        .map(|placable| placable.spawn(&mut commands, &textures, &mut materials))
+        .collect();
+    held.tile = save.held.map(|placable| placable.spawn(&mut commands, &textures, &mut materials));
+    held.swapped_this_turn = save.held_swapped_this_turn;
+    selected.0 = save.selected;
+    rotation.0 = save.rotation;
+    rng.0 = save.rng.clone();
+    results.0 = scenario.prize_entries.iter().enumerate()
+        .map(|(i, entry)| save.prize_scores.get(i).copied().flatten().map(|score| PrizeJudgement::new(entry, score)))
+        .collect();
+    *stats = save.stats.clone();
+    recorder.0 = save.replay.clone();
+    let resume = resume_point(&save.turn_state);
+    skip_autosave.0 = resume == TurnState::StartOfRound;
+    resume_turn.0 = Some(resume);
+
+    Ok(to_spawn)
+}
+
+/// Prepares the resources a saved game depends on and queues it up to be
+/// restored when the game starts.
+pub fn continue_saved_game(
+    loaded_game: &mut LoadedGame,
+    scenario: &mut Scenario,
+    difficulty: &mut Difficulty,
+    seed: &mut GameSeed,
+) -> Result<()> {
+    let save = load()?;
+    *scenario = Scenario::by_id(&save.scenario)
+        .ok_or_else(|| anyhow!("Unknown scenario {}", save.scenario))?;
+    *difficulty = save.difficulty;
+    seed.0 = save.seed;
+    loaded_game.0 = Some(save);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    const TILE: &str = "(pos: (5, 6), layer: Plants, sprite: \"carrot\", plant: Some(60), health: Some(1), \
+        rounds_till_mature: Some(2), prize_plant: None, fence: false, pest: None, idle_pest: false)";
+    const STATS: &str = "(rounds_survived: 2, plants_harvested: 1, plants_lost: 0, pests_repelled: 4)";
+    const REPLAY: &str = "(seed: 7, scenario: \"classic\", difficulty: Hard, actions: [Place((5, 6))])";
+
+    fn rng() -> String {
+        ron::ser::to_string(&ChaCha8Rng::seed_from_u64(7)).unwrap()
+    }
+
+    fn assert_common(save: &SaveGame) {
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.scenario, "classic");
+        assert_eq!(save.difficulty, Difficulty::Hard);
+        assert_eq!(save.seed, 7);
+        assert_eq!(save.turn_state, TurnState::PlayerTurn);
+        assert_eq!(save.tiles.len(), 1);
+        assert_eq!(save.tiles[0].pos, IVec2::new(5, 6));
+        assert_eq!(save.tiles[0].sprite, "carrot");
+        assert_eq!(save.queue, vec![PlacableTile::Radish, PlacableTile::Fence]);
+        assert_eq!(save.prize_scores, vec![Some(3), None]);
+        assert_eq!(save.stats.pests_repelled, 4);
+        assert_eq!(save.replay.actions.len(), 1);
+        assert_eq!(save.rotation, 0);
+    }
+
+    #[test]
+    fn reads_v1_save() {
+        let source = format!(
+            "(version: 1, scenario: \"classic\", difficulty: Hard, seed: 7, rng: {}, turn_state: PlayerTurn, \
+             tiles: [{}], queue: [Radish, Fence], prize_scores: [Some(3), None], stats: {}, replay: {})",
+            rng(), TILE, STATS, REPLAY,
+        );
+        let save = parse_save(&source).unwrap();
+        assert_common(&save);
+        assert_eq!(save.selected, 0);
+        assert_eq!(save.held, None);
+        assert!(!save.held_swapped_this_turn);
+    }
+
+    #[test]
+    fn reads_v2_save() {
+        let source = format!(
+            "(version: 2, scenario: \"classic\", difficulty: Hard, seed: 7, rng: {}, turn_state: PlayerTurn, \
+             tiles: [{}], queue: [Radish, Fence], selected: 1, held: Some(Pumpkin), held_swapped_this_turn: true, \
+             prize_scores: [Some(3), None], stats: {}, replay: {})",
+            rng(), TILE, STATS, REPLAY,
+        );
+        let save = parse_save(&source).unwrap();
+        assert_common(&save);
+        assert_eq!(save.selected, 1);
+        assert_eq!(save.held, Some(PlacableTile::Pumpkin));
+        assert!(save.held_swapped_this_turn);
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        assert!(parse_save("(version: 99)").is_err());
+    }
+}
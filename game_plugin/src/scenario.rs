@@ -0,0 +1,202 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::scoring::PrizeTier;
+
+/// A named set of rules for a run. High scores are kept per scenario.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub prize_entries: Vec<PrizeEntry>,
+    pub queue_rules: QueueRules,
+}
+
+/// Optional rules for the tile queue. A run uses a rule if either its
+/// scenario or its difficulty turns it on.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueRules {
+    /// The current tile can be swapped into a hold slot once per turn
+    pub hold_slot: bool,
+    /// Any of the visible tiles can be placed, not just the first one
+    pub pick_any: bool,
+}
+
+impl QueueRules {
+    pub fn or(self, other: QueueRules) -> QueueRules {
+        QueueRules {
+            hold_slot: self.hold_slot || other.hold_slot,
+            pick_any: self.pick_any || other.pick_any,
+        }
+    }
+}
+
+/// One plant entered into the show. An entry can cover several tiles, which
+/// are judged together once the entry matures.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrizeEntry {
+    pub name: &'static str,
+    pub tiles: Vec<(IVec2, &'static str)>,
+    pub health_per_tile: i32,
+    pub rounds_till_mature: i32,
+    pub thresholds: PrizeThresholds,
+}
+
+impl PrizeEntry {
+    fn giant_pumpkin(corner: IVec2) -> Self {
+        PrizeEntry {
+            name: "Giant pumpkin",
+            tiles: vec![
+                (corner, "big_pumpkin1"),
+                (corner + IVec2::new(1, 0), "big_pumpkin2"),
+                (corner + IVec2::new(1, 1), "big_pumpkin3"),
+                (corner + IVec2::new(0, 1), "big_pumpkin4"),
+            ],
+            health_per_tile: 10,
+            rounds_till_mature: 10,
+            thresholds: PrizeThresholds {
+                first: 40,
+                second: 24,
+                third: 1,
+            },
+        }
+    }
+
+    fn giant_carrot(position: IVec2) -> Self {
+        PrizeEntry {
+            name: "Giant carrot",
+            tiles: vec![(position, "carrot")],
+            health_per_tile: 6,
+            rounds_till_mature: 8,
+            thresholds: PrizeThresholds {
+                first: 6,
+                second: 4,
+                third: 1,
+            },
+        }
+    }
+
+    pub fn max_score(&self) -> u32 {
+        self.tiles.len() as u32 * self.health_per_tile.max(0) as u32
+    }
+}
+
+/// The lowest combined health an entry needs to win each prize.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PrizeThresholds {
+    pub first: u32,
+    pub second: u32,
+    pub third: u32,
+}
+
+impl PrizeThresholds {
+    pub fn tier(&self, score: u32) -> PrizeTier {
+        if score >= self.first {
+            PrizeTier::First
+        } else if score >= self.second {
+            PrizeTier::Second
+        } else if score >= self.third {
+            PrizeTier::Third
+        } else {
+            PrizeTier::NoPrize
+        }
+    }
+}
+
+impl Scenario {
+    pub fn classic() -> Self {
+        Scenario {
+            id: "classic",
+            name: "Classic",
+            prize_entries: vec![PrizeEntry::giant_pumpkin(IVec2::new(5, 5))],
+            queue_rules: QueueRules::default(),
+        }
+    }
+
+    pub fn county_fair() -> Self {
+        Scenario {
+            id: "county_fair",
+            name: "County Fair",
+            prize_entries: vec![
+                PrizeEntry::giant_pumpkin(IVec2::new(5, 5)),
+                PrizeEntry::giant_carrot(IVec2::new(8, 3)),
+            ],
+            queue_rules: QueueRules {
+                hold_slot: true,
+                pick_any: false,
+            },
+        }
+    }
+
+    pub fn all() -> Vec<Scenario> {
+        vec![Scenario::classic(), Scenario::county_fair()]
+    }
+
+    pub fn by_id(id: &str) -> Option<Scenario> {
+        Scenario::all().into_iter().find(|s| s.id == id)
+    }
+
+    pub fn queue_rules_for(&self, difficulty: Difficulty) -> QueueRules {
+        self.queue_rules.or(difficulty.queue_rules())
+    }
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario::classic()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn all() -> [Difficulty; 3] {
+        [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    /// How many pests arrive at the start of each round.
+    pub fn pest_count(&self) -> std::ops::Range<usize> {
+        match self {
+            Difficulty::Easy => 1..3,
+            Difficulty::Normal => 1..4,
+            Difficulty::Hard => 2..5,
+        }
+    }
+
+    /// Chance that a pest is a gust of wind rather than a rabbit.
+    pub fn wind_chance(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.05,
+            Difficulty::Normal => 0.1,
+            Difficulty::Hard => 0.2,
+        }
+    }
+
+    pub fn queue_rules(&self) -> QueueRules {
+        match self {
+            Difficulty::Easy => QueueRules {
+                hold_slot: true,
+                pick_any: true,
+            },
+            Difficulty::Normal | Difficulty::Hard => QueueRules::default(),
+        }
+    }
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::Normal
+    }
+}
@@ -1,28 +1,65 @@
 use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
 use crate::{
     GameState,
     loading::TextureAssets,
     turn_structure::TurnState,
     main_ui::{despawn_overlay, GameOverlay},
     plants::{Plant, RoundsTillMature},
+    judging::PrizeResults,
+    high_scores::LatestHighScore,
+    replay::ReplayRecorder,
+    rng::GameSeed,
+    save_game::new_run,
+    transitions::Transitions,
 };
 
 pub struct ScoringPlugin;
-#[derive(Default)]
-pub struct PrizePlantScore(pub u32);
-struct PrizeScreenTimer(Timer);
+
+/// Tallies for the results screen, reset at the start of every run.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RunStats {
+    pub rounds_survived: u32,
+    pub plants_harvested: u32,
+    pub plants_lost: u32,
+    pub pests_repelled: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum PrizeTier {
+    NoPrize,
+    Third,
+    Second,
+    First,
+}
+
+impl PrizeTier {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PrizeTier::NoPrize => "No prize",
+            PrizeTier::Third => "3rd prize",
+            PrizeTier::Second => "2nd prize",
+            PrizeTier::First => "1st prize",
+        }
+    }
+}
 
 impl Plugin for ScoringPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<PrizePlantScore>();
-        app.insert_resource(PrizeScreenTimer(Timer::from_seconds(2.0, true)));
+        app.init_resource::<RunStats>();
+        app.add_system_set(
+            new_run()
+                .with_system(reset_run_stats.system())
+        );
         app.add_system_set(
             SystemSet::on_enter(TurnState::EndOfRound)
                 .with_system(score.system().after("incr_maturity"))
+                .with_system(count_round.system())
         );
         app.add_system_set(
             SystemSet::on_enter(GameState::PrizePlantScoring)
-                .with_system(score_prize_plant.system().after("incr_maturity"))
+                .with_system(score_prize_plant.system())
         );
         app.add_system_set(
             SystemSet::on_exit(GameState::PrizePlantScoring)
@@ -30,7 +67,7 @@ impl Plugin for ScoringPlugin {
         );
         app.add_system_set(
             SystemSet::on_update(GameState::PrizePlantScoring)
-                .with_system(score_screen_timer.system())
+                .with_system(results_screen.system())
         );
     }
 }
@@ -47,20 +84,102 @@ fn score(
     println!("Round score: {}", score);
 }
 
-fn score_screen_timer(
-    mut state: ResMut<State<GameState>>,
-    time: Res<Time>,
-    mut prize_screen_timer: ResMut<PrizeScreenTimer>,
+fn reset_run_stats(
+    mut stats: ResMut<RunStats>,
 ) {
-    prize_screen_timer.0.tick(time.delta());
-    if prize_screen_timer.0.just_finished() {
-        state.set(GameState::Menu);
+    *stats = RunStats::default();
+}
+
+fn count_round(
+    mut stats: ResMut<RunStats>,
+) {
+    stats.rounds_survived += 1;
+}
+
+fn results_screen(
+    egui_context: Res<EguiContext>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    results: Res<PrizeResults>,
+    stats: Res<RunStats>,
+    seed: Res<GameSeed>,
+    latest: Res<LatestHighScore>,
+    recorder: Res<ReplayRecorder>,
+    mut saved_replay: Local<Option<String>>,
+    mut transitions: ResMut<Transitions>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        *saved_replay = None;
+        transitions.set_game(GameState::Menu);
+        return;
     }
+    egui::Window::new("Results")
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -20.0])
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_context.ctx(), |ui| {
+            ui.heading(results.best_tier().name());
+            if let Some(rank) = latest.rank {
+                ui.colored_label(egui::Color32::YELLOW, format!("New high score! #{} for this scenario", rank + 1));
+            }
+            ui.separator();
+            egui::Grid::new("score_breakdown").show(ui, |ui| {
+                for judgement in results.judged() {
+                    ui.label(judgement.entry);
+                    ui.label(format!("{}/{}", judgement.score, judgement.max_score));
+                    ui.label(judgement.tier.name());
+                    ui.end_row();
+                }
+                ui.strong("Total");
+                ui.strong(results.total_score());
+                ui.end_row();
+            });
+            ui.separator();
+            egui::Grid::new("run_stats").show(ui, |ui| {
+                ui.label("Rounds survived");
+                ui.label(stats.rounds_survived);
+                ui.end_row();
+                ui.label("Plants harvested");
+                ui.label(stats.plants_harvested);
+                ui.end_row();
+                ui.label("Plants lost");
+                ui.label(stats.plants_lost);
+                ui.end_row();
+                ui.label("Pests repelled");
+                ui.label(stats.pests_repelled);
+                ui.end_row();
+                ui.label("Seed");
+                ui.label(seed.0);
+                ui.end_row();
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Retry same seed").clicked() {
+                    *saved_replay = None;
+                    transitions.set_game(GameState::Playing);
+                }
+                if ui.button("New game").clicked() {
+                    *saved_replay = None;
+                    // Under the menu, so backing out of the page lands there
+                    transitions.set_game(GameState::Menu);
+                    transitions.push_game(GameState::NewGame);
+                }
+                if ui.button("Save replay").clicked() {
+                    match recorder.save() {
+                        Ok(key) => *saved_replay = Some(format!("Saved as {}", key)),
+                        Err(e) => *saved_replay = Some(format!("Could not save replay: {}", e)),
+                    }
+                }
+            });
+            if let Some(message) = saved_replay.as_ref() {
+                ui.label(message);
+            }
+        });
 }
 
 fn score_prize_plant(
     mut commands: Commands,
-    score: Res<PrizePlantScore>,
+    results: Res<PrizeResults>,
     textures: Res<TextureAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
@@ -69,31 +188,17 @@ fn score_prize_plant(
         transform: Transform::from_xyz(0.0, 0.0, 100.0),
         ..Default::default()
     }).insert(GameOverlay);
-    if score.0 == 0 {
-        commands.spawn_bundle(SpriteBundle {
-            material: materials.add(textures.no_prize.clone().into()),
-            transform: Transform::from_xyz(0.0, 0.0, 0.0),
-            ..Default::default()
-        }).insert(GameOverlay);
-    } else if score.0 == 10 {
-        commands.spawn_bundle(SpriteBundle {
-            material: materials.add(textures.first_prize.clone().into()),
-            transform: Transform::from_xyz(0.0, 0.0, 0.0),
-            ..Default::default()
-        }).insert(GameOverlay);
-    } else if score.0 > 5 {
-        commands.spawn_bundle(SpriteBundle {
-            material: materials.add(textures.second_prize.clone().into()),
-            transform: Transform::from_xyz(0.0, 0.0, 0.0),
-            ..Default::default()
-        }).insert(GameOverlay);
-    } else {
-        commands.spawn_bundle(SpriteBundle {
-            material: materials.add(textures.third_prize.clone().into()),
-            transform: Transform::from_xyz(0.0, 0.0, 0.0),
-            ..Default::default()
-        }).insert(GameOverlay);
-    }
+    let texture = match results.best_tier() {
+        PrizeTier::NoPrize => textures.no_prize.clone(),
+        PrizeTier::Third => textures.third_prize.clone(),
+        PrizeTier::Second => textures.second_prize.clone(),
+        PrizeTier::First => textures.first_prize.clone(),
+    };
+    commands.spawn_bundle(SpriteBundle {
+        material: materials.add(texture.into()),
+        transform: Transform::from_xyz(0.0, 0.0, 0.0),
+        ..Default::default()
+    }).insert(GameOverlay);
 
-    println!("Prize Plant Score: {}", score.0);
+    println!("Prize Plant Score: {}", results.total_score());
 }
@@ -0,0 +1,276 @@
+use bevy::{prelude::*, window::WindowMode};
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+use game_music::AudioOptions;
+use crate::{
+    GameState,
+    persistence,
+    menu::button_list,
+    turn_structure::TURN_STEP,
+    transitions::Transitions,
+};
+
+const SETTINGS_KEY: &str = "settings";
+
+/// Player preferences, persisted whenever they are changed on the settings page.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub muted: bool,
+    /// Fade the audio out while the window is in the background
+    pub pause_audio_when_unfocused: bool,
+    /// Open an audio device at all. Only read at startup.
+    pub audio_enabled: bool,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// Move pests straight to their new tile instead of animating them
+    pub skip_animations: bool,
+    pub game_speed: GameSpeed,
+}
+
+/// How quickly the pests take their turns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameSpeed {
+    Slow,
+    Normal,
+    Fast,
+    /// As fast as the frame rate allows, without animations
+    Instant,
+}
+
+impl GameSpeed {
+    pub fn all() -> [GameSpeed; 4] {
+        [GameSpeed::Slow, GameSpeed::Normal, GameSpeed::Fast, GameSpeed::Instant]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameSpeed::Slow => "Slow",
+            GameSpeed::Normal => "Normal",
+            GameSpeed::Fast => "Fast",
+            GameSpeed::Instant => "Instant",
+        }
+    }
+
+    /// Turn steps between pest ticks, or `None` to tick every step.
+    pub fn tick_steps(&self) -> Option<u32> {
+        match self {
+            GameSpeed::Slow => Some(15),
+            GameSpeed::Normal => Some(6),
+            GameSpeed::Fast => Some(3),
+            GameSpeed::Instant => None,
+        }
+    }
+
+    pub fn tick_seconds(&self) -> Option<f32> {
+        self.tick_steps().map(|steps| steps as f32 * TURN_STEP)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            master_volume: 1.0,
+            music_volume: 0.5,
+            sfx_volume: 0.5,
+            muted: false,
+            pause_audio_when_unfocused: true,
+            audio_enabled: true,
+            fullscreen: false,
+            vsync: true,
+            skip_animations: false,
+            game_speed: GameSpeed::Normal,
+        }
+    }
+}
+
+impl Settings {
+    fn load() -> Self {
+        persistence::load(SETTINGS_KEY).unwrap_or_default()
+    }
+
+    fn save(&self) {
+        if let Err(e) = persistence::save(SETTINGS_KEY, self) {
+            warn!("Could not save settings: {}", e);
+        }
+    }
+
+    /// `--no-audio` on the command line or `RABBIT_GARDEN_NO_AUDIO` in the
+    /// environment turn audio off whatever the setting says, for machines
+    /// without a sound card.
+    fn audio_options(&self) -> AudioOptions {
+        let disabled = std::env::args().any(|arg| arg == "--no-audio")
+            || std::env::var_os("RABBIT_GARDEN_NO_AUDIO").is_some();
+        AudioOptions {
+            enabled: self.audio_enabled && !disabled,
+        }
+    }
+}
+
+/// Human readable list of the bindings shown on the controls tab.
+pub const CONTROLS: &[(&str, &str)] = &[
+    ("Left click", "Place the next tile"),
+    ("Arrows, WASD, d-pad", "Move the tile cursor"),
+    ("Enter, Space, pad A", "Place at the tile cursor"),
+    ("R, mouse wheel, pad Y", "Rotate the next piece"),
+    ("H, click the hold slot, pad X", "Hold the current tile (when the hold slot is on)"),
+    ("1-5, Q/E, click a queued tile, bumpers", "Pick the tile to place (when picking any tile is on)"),
+    ("Tap a tile twice", "Preview, then place there"),
+    ("Drag, pinch", "Pan and zoom the board"),
+    ("Hold F, hold pad RT", "Fast-forward the pests"),
+    ("F6", "Toggle step mode (debug)"),
+    ("N", "Next pest tick in step mode"),
+    ("Escape", "Back / pause"),
+    ("Tab, Up, Down", "Move between menu buttons"),
+    ("Enter, Space", "Activate the selected button"),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SettingsTab {
+    Audio,
+    Display,
+    Gameplay,
+    Controls,
+}
+
+impl SettingsTab {
+    fn all() -> [SettingsTab; 4] {
+        [SettingsTab::Audio, SettingsTab::Display, SettingsTab::Gameplay, SettingsTab::Controls]
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            SettingsTab::Audio => "Audio",
+            SettingsTab::Display => "Display",
+            SettingsTab::Gameplay => "Gameplay",
+            SettingsTab::Controls => "Controls",
+        }
+    }
+}
+
+impl Default for SettingsTab {
+    fn default() -> Self {
+        SettingsTab::Audio
+    }
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let settings = Settings::load();
+        app.insert_resource(settings.audio_options());
+        app.insert_resource(settings);
+        app.add_system_set(
+            SystemSet::on_update(GameState::Settings)
+                .with_system(settings_ui.system())
+        );
+        app.add_system(apply_display_settings.system());
+    }
+}
+
+/// Changes take effect straight away, but are only written out once a
+/// slider is let go of, a box is ticked or the page is left, so dragging a
+/// slider doesn't save every frame.
+fn settings_ui(
+    egui_context: Res<EguiContext>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    mut tab: Local<SettingsTab>,
+    mut selected: Local<usize>,
+    mut unsaved: Local<bool>,
+    mut transitions: ResMut<Transitions>,
+) {
+    let mut leave = keyboard_input.just_pressed(KeyCode::Escape);
+    if leave {
+        keyboard_input.reset(KeyCode::Escape);
+    }
+    let mut edited = settings.clone();
+    let mut commit = false;
+    egui::SidePanel::left("settings_tabs").show(egui_context.ctx(), |ui| {
+        ui.heading("Settings");
+        ui.add_space(20.0);
+        let tabs = SettingsTab::all();
+        let mut buttons: Vec<(&str, bool)> = tabs.iter().map(|t| (t.name(), true)).collect();
+        buttons.push(("Back", true));
+        match button_list(ui, &mut keyboard_input, &mut selected, &buttons) {
+            Some(i) if i < tabs.len() => *tab = tabs[i],
+            Some(_) => leave = true,
+            None => (),
+        }
+    });
+    egui::CentralPanel::default().show(egui_context.ctx(), |ui| {
+        ui.heading(tab.name());
+        ui.separator();
+        match *tab {
+            SettingsTab::Audio => {
+                for (volume, label) in [
+                    (&mut edited.master_volume, "Master volume"),
+                    (&mut edited.music_volume, "Music volume"),
+                    (&mut edited.sfx_volume, "Effects volume"),
+                ] {
+                    let response = ui.add(egui::Slider::new(volume, 0.0..=1.0).text(label));
+                    commit |= response.drag_released() || (response.changed() && !response.dragged());
+                }
+                commit |= ui.checkbox(&mut edited.muted, "Mute").changed();
+                commit |= ui.checkbox(&mut edited.pause_audio_when_unfocused, "Pause audio in the background").changed();
+                commit |= ui.checkbox(&mut edited.audio_enabled, "Enable audio (after a restart)").changed();
+            }
+            SettingsTab::Display => {
+                commit |= ui.checkbox(&mut edited.fullscreen, "Fullscreen").changed();
+                commit |= ui.checkbox(&mut edited.vsync, "VSync").changed();
+            }
+            SettingsTab::Gameplay => {
+                ui.label("Game speed");
+                ui.horizontal(|ui| {
+                    for speed in GameSpeed::all().iter() {
+                        commit |= ui.selectable_value(&mut edited.game_speed, *speed, speed.name()).changed();
+                    }
+                });
+                commit |= ui.checkbox(&mut edited.skip_animations, "Skip animations").changed();
+            }
+            SettingsTab::Controls => {
+                egui::Grid::new("controls").show(ui, |ui| {
+                    for (binding, action) in CONTROLS {
+                        ui.strong(binding);
+                        ui.label(action);
+                        ui.end_row();
+                    }
+                });
+            }
+        }
+    });
+    if edited != *settings {
+        *settings = edited;
+        *unsaved = true;
+    }
+    if *unsaved && (commit || leave) {
+        settings.save();
+        *unsaved = false;
+    }
+    if leave {
+        transitions.pop_game();
+    }
+}
+
+fn apply_display_settings(
+    settings: Res<Settings>,
+    mut windows: ResMut<Windows>,
+) {
+    if let Some(window) = windows.get_primary_mut() {
+        let mode = if settings.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        };
+        if window.mode() != mode {
+            window.set_mode(mode);
+        }
+        if window.vsync() != settings.vsync {
+            window.set_vsync(settings.vsync);
+        }
+    }
+}
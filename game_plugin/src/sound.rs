@@ -0,0 +1,80 @@
+use bevy::{prelude::*, window::WindowFocused};
+use game_music::{AudioCommand, AudioCommands, Sfx, SoundEffects};
+use crate::{
+    events::GameEvent,
+    scoring::PrizeTier,
+    settings::Settings,
+};
+
+/// Plays sound effects for gameplay events and keeps the mix in line with
+/// the audio settings and the window's focus.
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(play_sound_effects.system());
+        app.add_system(apply_volumes.system());
+        app.add_system(pause_when_unfocused.system());
+    }
+}
+
+fn play_sound_effects(
+    effects: Res<SoundEffects>,
+    mut events: EventReader<GameEvent>,
+) {
+    let mut gust = false;
+    for event in events.iter() {
+        let sfx = match event {
+            GameEvent::TilePlaced { .. } => Sfx::Place,
+            GameEvent::PlacementRejected { .. } => Sfx::Invalid,
+            GameEvent::PlantDamaged { .. } | GameEvent::PlantEaten { .. } => Sfx::Munch,
+            GameEvent::FenceDestroyed { .. } => Sfx::FenceSmash,
+            GameEvent::PlantMatured { .. } => Sfx::Harvest,
+            GameEvent::PrizeJudged { tier, .. } if *tier != PrizeTier::NoPrize => Sfx::Fanfare,
+            GameEvent::PestSpawned { wind: true, .. } => {
+                // One gust however many winds blow in
+                gust = true;
+                continue;
+            }
+            _ => continue,
+        };
+        effects.play(sfx);
+    }
+    if gust {
+        effects.play(Sfx::Gust);
+    }
+}
+
+fn apply_volumes(
+    settings: Res<Settings>,
+    mut commands: ResMut<AudioCommands>,
+) {
+    if settings.is_changed() {
+        commands.send(AudioCommand::MasterVolume(settings.master_volume));
+        commands.send(AudioCommand::MusicVolume(settings.music_volume));
+        commands.send(AudioCommand::SfxVolume(settings.sfx_volume));
+        commands.send(AudioCommand::Mute(settings.muted));
+    }
+}
+
+#[derive(Default)]
+struct Focus {
+    lost: bool,
+    paused: bool,
+}
+
+fn pause_when_unfocused(
+    settings: Res<Settings>,
+    mut commands: ResMut<AudioCommands>,
+    mut focus_events: EventReader<WindowFocused>,
+    mut focus: Local<Focus>,
+) {
+    for event in focus_events.iter() {
+        focus.lost = !event.focused;
+    }
+    let paused = focus.lost && settings.pause_audio_when_unfocused;
+    if paused != focus.paused {
+        commands.send(AudioCommand::Pause(paused));
+        focus.paused = paused;
+    }
+}
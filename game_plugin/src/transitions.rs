@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use crate::{
+    GameState,
+    turn_structure::{turn_step, TurnState},
+};
+
+/// A requested change to `GameState` or `TurnState`. Systems queue these on
+/// `Transitions` instead of touching the states directly, and they're
+/// checked against the tables below before being applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transition {
+    Turn(TurnState),
+    SetGame(GameState),
+    PushGame(GameState),
+    ReplaceGame(GameState),
+    PopGame,
+}
+
+/// Queue of transitions waiting to be applied, oldest first.
+#[derive(Default)]
+pub struct Transitions(VecDeque<Transition>);
+
+impl Transitions {
+    pub fn request(&mut self, transition: Transition) {
+        self.0.push_back(transition);
+    }
+
+    pub fn set_turn(&mut self, state: TurnState) {
+        self.request(Transition::Turn(state));
+    }
+
+    pub fn set_game(&mut self, state: GameState) {
+        self.request(Transition::SetGame(state));
+    }
+
+    pub fn push_game(&mut self, state: GameState) {
+        self.request(Transition::PushGame(state));
+    }
+
+    pub fn replace_game(&mut self, state: GameState) {
+        self.request(Transition::ReplaceGame(state));
+    }
+
+    pub fn pop_game(&mut self) {
+        self.request(Transition::PopGame);
+    }
+}
+
+/// Which `TurnState` may follow which. Leaving `Idle` for anything other
+/// than `RoundSetup` is how a restored game resumes mid round.
+fn turn_allowed(from: &TurnState, to: &TurnState) -> bool {
+    use TurnState::*;
+    match (from, to) {
+        (Idle, Idle) => false,
+        (_, Idle) => true,
+        (Idle, _) => true,
+        (RoundSetup, StartOfRound) => true,
+        (StartOfRound, PlayerTurn) => true,
+        (PlayerTurn, PestTurnA) => true,
+        (PestTurnA, PestTurnB) => true,
+        (PestTurnA, EndOfRound) => true,
+        (PestTurnB, PestTurnA) => true,
+        (EndOfRound, RoundCleanup) => true,
+        (RoundCleanup, RoundSetup) => true,
+        _ => false,
+    }
+}
+
+/// Which changes each `GameState` allows. Pages of the menu and the pause
+/// screen are pushed, so they're left with a pop.
+fn game_allowed(from: &GameState, transition: &Transition) -> bool {
+    use GameState::*;
+    match (from, transition) {
+        (Menu, Transition::PushGame(NewGame))
+        | (Menu, Transition::PushGame(Settings))
+        | (Menu, Transition::PushGame(HighScores))
+        | (Menu, Transition::PushGame(Credits))
+        | (Menu, Transition::SetGame(Playing)) => true,
+        (NewGame, Transition::ReplaceGame(Playing)) => true,
+        (Playing, Transition::PushGame(Paused))
+        | (Playing, Transition::SetGame(PrizePlantScoring)) => true,
+        (Paused, Transition::PushGame(Settings))
+        | (Paused, Transition::ReplaceGame(Playing))
+        | (Paused, Transition::ReplaceGame(Menu)) => true,
+        (PrizePlantScoring, Transition::SetGame(Menu))
+        | (PrizePlantScoring, Transition::SetGame(Playing)) => true,
+        (NewGame, Transition::PopGame)
+        | (Settings, Transition::PopGame)
+        | (HighScores, Transition::PopGame)
+        | (Credits, Transition::PopGame)
+        | (Paused, Transition::PopGame) => true,
+        _ => false,
+    }
+}
+
+pub struct TransitionPlugin;
+
+impl Plugin for TransitionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Transitions>();
+        // Applied on turn steps in `Update`, where the state drivers run, so
+        // several transitions can resolve within one long frame.
+        app.add_system_set(
+            turn_step()
+                .with_system(apply_transitions.system().label("apply transitions"))
+        );
+    }
+}
+
+/// Applies queued transitions in order. Bevy only takes one pending change
+/// per state at a time, so after a change to either state the rest of the
+/// requests for it wait for the next step.
+fn apply_transitions(
+    mut transitions: ResMut<Transitions>,
+    mut game_state: ResMut<State<GameState>>,
+    mut turn_state: ResMut<State<TurnState>>,
+) {
+    let mut game_waiting = false;
+    let mut turn_waiting = false;
+    let mut discard_turns = false;
+    let mut remaining = VecDeque::new();
+    for transition in std::mem::take(&mut transitions.0) {
+        match transition {
+            Transition::Turn(_) if discard_turns => {
+                // Worked out from the game that just ended
+                info!("Discarded stale turn transition {:?}", transition);
+            }
+            Transition::Turn(_) if turn_waiting => remaining.push_back(transition),
+            Transition::Turn(to) => {
+                let from = turn_state.current().clone();
+                if to != TurnState::Idle && game_state.current() != &GameState::Playing {
+                    if game_state.inactives().contains(&GameState::Playing) {
+                        // Paused: hold on to it until play carries on
+                        remaining.push_back(Transition::Turn(to));
+                        turn_waiting = true;
+                    } else {
+                        warn!("Dropped turn transition {:?} -> {:?} outside of play", from, to);
+                    }
+                    continue;
+                }
+                if !turn_allowed(&from, &to) {
+                    warn!("Rejected turn transition {:?} -> {:?}", from, to);
+                    continue;
+                }
+                match turn_state.set(to.clone()) {
+                    Ok(()) => {
+                        info!("Turn transition {:?} -> {:?}", from, to);
+                        turn_waiting = true;
+                        discard_turns = to == TurnState::Idle;
+                    }
+                    Err(e) => warn!("Failed turn transition {:?} -> {:?}: {:?}", from, to, e),
+                }
+            }
+            transition if game_waiting => remaining.push_back(transition),
+            transition => {
+                let from = game_state.current().clone();
+                if !game_allowed(&from, &transition) {
+                    warn!("Rejected game transition {:?} from {:?}", transition, from);
+                    continue;
+                }
+                let result = match transition.clone() {
+                    Transition::SetGame(to) => game_state.set(to),
+                    Transition::PushGame(to) => game_state.push(to),
+                    Transition::ReplaceGame(to) => game_state.replace(to),
+                    Transition::PopGame => game_state.pop(),
+                    Transition::Turn(_) => unreachable!(),
+                };
+                match result {
+                    Ok(()) => {
+                        info!("Game transition {:?} from {:?}", transition, from);
+                        game_waiting = true;
+                    }
+                    Err(e) => warn!("Failed game transition {:?} from {:?}: {:?}", transition, from, e),
+                }
+            }
+        }
+    }
+    transitions.0 = remaining;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turn_follows_the_round() {
+        use TurnState::*;
+        let round = [Idle, RoundSetup, StartOfRound, PlayerTurn, PestTurnA, PestTurnB, PestTurnA, EndOfRound, RoundCleanup, RoundSetup];
+        for pair in round.windows(2) {
+            assert!(turn_allowed(&pair[0], &pair[1]), "{:?} -> {:?}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn turn_resumes_from_idle_and_resets_to_it() {
+        use TurnState::*;
+        for state in [StartOfRound, PlayerTurn, PestTurnB, RoundCleanup].iter() {
+            assert!(turn_allowed(&Idle, state));
+            assert!(turn_allowed(state, &Idle));
+        }
+        assert!(!turn_allowed(&Idle, &Idle));
+    }
+
+    #[test]
+    fn turn_rejects_skipping_ahead() {
+        use TurnState::*;
+        assert!(!turn_allowed(&PlayerTurn, &EndOfRound));
+        assert!(!turn_allowed(&PestTurnB, &EndOfRound));
+        assert!(!turn_allowed(&RoundSetup, &PlayerTurn));
+        assert!(!turn_allowed(&EndOfRound, &RoundSetup));
+    }
+
+    #[test]
+    fn game_allows_menu_pages_and_play() {
+        use GameState::*;
+        let allowed = [
+            (Menu, Transition::PushGame(NewGame)),
+            (Menu, Transition::PushGame(Settings)),
+            (Menu, Transition::PushGame(HighScores)),
+            (Menu, Transition::PushGame(Credits)),
+            (Menu, Transition::SetGame(Playing)),
+            (NewGame, Transition::ReplaceGame(Playing)),
+            (NewGame, Transition::PopGame),
+            (Playing, Transition::PushGame(Paused)),
+            (Playing, Transition::SetGame(PrizePlantScoring)),
+            (Paused, Transition::PushGame(Settings)),
+            (Paused, Transition::ReplaceGame(Playing)),
+            (Paused, Transition::ReplaceGame(Menu)),
+            (Paused, Transition::PopGame),
+            (Settings, Transition::PopGame),
+            (PrizePlantScoring, Transition::SetGame(Menu)),
+            (PrizePlantScoring, Transition::SetGame(Playing)),
+        ];
+        for (from, transition) in allowed.iter() {
+            assert!(game_allowed(from, transition), "{:?} from {:?}", transition, from);
+        }
+    }
+
+    #[test]
+    fn game_rejects_skipping_the_menu() {
+        use GameState::*;
+        assert!(!game_allowed(&Loading, &Transition::SetGame(Playing)));
+        assert!(!game_allowed(&Playing, &Transition::PopGame));
+        assert!(!game_allowed(&Menu, &Transition::PopGame));
+        assert!(!game_allowed(&PrizePlantScoring, &Transition::PushGame(Paused)));
+    }
+}
@@ -1,15 +1,23 @@
 use bevy::{
     prelude::*,
+    ecs::schedule::ShouldRun,
     input::{
         keyboard::KeyboardInput,
         ElementState,
     }
 };
-use crate::GameState;
+use serde::{Deserialize, Serialize};
+use crate::{
+    GameState,
+    animation::Tween,
+    events::GameEvent,
+    settings::{GameSpeed, Settings},
+    transitions::Transitions,
+};
 
 pub struct TurnPlugin;
 
-#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+#[derive(Clone, Eq, PartialEq, Debug, Hash, Serialize, Deserialize)]
 pub enum TurnState {
     Idle,
     RoundSetup,
@@ -24,50 +32,263 @@ pub enum TurnState {
 impl Plugin for TurnPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app
-            .insert_resource(TurnTimer(Timer::from_seconds(0.05, true)))
+            .init_resource::<TurnClock>()
+            .init_resource::<RoundOver>()
+            .init_resource::<RunOver>()
+            .init_resource::<LastRoundEnd>()
+            .init_resource::<ResumeTurn>()
+            .init_resource::<TurnSpeed>()
+            .init_resource::<StepMode>()
             .add_state(TurnState::Idle)
            .add_system_set(
                SystemSet::on_update(GameState::Playing)
-                   .with_system(progress_turn.system())
+                   .with_system(control_turn_speed.system().label("control turn speed"))
+           )
+           .add_system_set(
+               SystemSet::new()
+                   .with_run_criteria(RunCriteria::pipe("turn step", while_playing.system()))
+                   .with_system(progress_turn.system().label("progress turn").after("control turn speed").before("apply transitions"))
+           )
+           .add_system_set(
+               SystemSet::on_enter(TurnState::PestTurnA)
+                   .with_system(start_pest_tick.system())
            )
            .add_system_set(
                SystemSet::on_exit(GameState::Playing)
                    .with_system(reset_turn_state.system())
+           )
+           .add_system_set(
+               SystemSet::on_enter(TurnState::StartOfRound)
+                   .with_system(announce_round_start.system())
            );
     }
 }
 
-struct TurnTimer(Timer);
+/// Length of one step of the turn clock. The turn pipeline, from
+/// `progress_turn` to applying the transitions it asks for, runs once per
+/// step however long the frame was, so rounds take the same time and play
+/// out the same at any frame rate.
+pub const TURN_STEP: f32 = 1.0 / 60.0;
 
-fn progress_turn(
+/// Never catch up on more than this many steps after a long frame.
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+/// Accumulates frame time into whole `TURN_STEP`s, and counts the steps
+/// between pest ticks.
+#[derive(Default)]
+struct TurnClock {
+    accumulator: f32,
+    /// Part way through running this frame's steps
+    stepping: bool,
+    steps_since_tick: u32,
+}
+
+/// Systems run once per `TURN_STEP`, several times in a long frame. Only one
+/// set may use this, it labels the criteria the turn pipeline pipes from.
+pub fn turn_step() -> SystemSet {
+    SystemSet::new().with_run_criteria(run_turn_steps.system().label("turn step"))
+}
+
+/// Works like `FixedTimestep`, but never catches up on more than
+/// `MAX_STEPS_PER_FRAME`, so a frame after the game was hidden for a while
+/// doesn't run through whole rounds.
+fn run_turn_steps(
     time: Res<Time>,
-    mut turn_timer: ResMut<TurnTimer>,
+    mut clock: ResMut<TurnClock>,
+) -> ShouldRun {
+    if !clock.stepping {
+        clock.accumulator = (clock.accumulator + time.delta_seconds()).min(MAX_STEPS_PER_FRAME as f32 * TURN_STEP);
+    }
+    if clock.accumulator >= TURN_STEP {
+        clock.accumulator -= TURN_STEP;
+        clock.stepping = true;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        clock.stepping = false;
+        ShouldRun::No
+    }
+}
+
+/// The turn only moves on while the game is being played, not under the
+/// pause menu.
+fn while_playing(
+    In(step): In<ShouldRun>,
+    state: Res<State<GameState>>,
+) -> ShouldRun {
+    if state.current() == &GameState::Playing {
+        return step;
+    }
+    match step {
+        ShouldRun::YesAndCheckAgain | ShouldRun::NoAndCheckAgain => ShouldRun::NoAndCheckAgain,
+        ShouldRun::Yes | ShouldRun::No => ShouldRun::No,
+    }
+}
+
+/// Why a round's pest phase came to an end.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RoundEndReason {
+    /// Every pest left the board, was stopped or gave up
+    PestsGone,
+    /// The pests got stuck in a loop that would never finish
+    Stalemate,
+    /// The pests ran out of ticks for the round
+    OutOfTime,
+}
+
+impl RoundEndReason {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            RoundEndReason::PestsGone => "The pests have all gone",
+            RoundEndReason::Stalemate => "The pests were going round in circles",
+            RoundEndReason::OutOfTime => "The pests ran out of time",
+        }
+    }
+}
+
+/// Set by the pests once the round should end. `progress_turn` acts on it,
+/// so the pest phase only has one system changing `TurnState`.
+#[derive(Default)]
+pub struct RoundOver(pub Option<RoundEndReason>);
+
+/// Set once every prize entry has been judged. `progress_turn` ends the run
+/// after `RoundCleanup`, so the last round's harvest still counts.
+#[derive(Default)]
+pub struct RunOver(pub bool);
+
+/// How the last round ended, for the UI.
+#[derive(Default)]
+pub struct LastRoundEnd(pub Option<RoundEndReason>);
+
+/// The speed pests are moving at right now: the setting, unless the player
+/// is holding fast-forward.
+pub struct TurnSpeed(pub GameSpeed);
+
+impl Default for TurnSpeed {
+    fn default() -> Self {
+        TurnSpeed(GameSpeed::Normal)
+    }
+}
+
+/// Debug mode where each pest tick waits for the step key.
+#[derive(Default)]
+pub struct StepMode {
+    pub enabled: bool,
+    step_requested: bool,
+}
+
+fn control_turn_speed(
+    settings: Res<Settings>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut speed: ResMut<TurnSpeed>,
+    mut step_mode: ResMut<StepMode>,
+) {
+    let fast_forward = keyboard_input.pressed(KeyCode::F)
+        || gamepad_buttons.get_pressed().any(|b| b.1 == GamepadButtonType::RightTrigger2);
+    let wanted = if fast_forward { GameSpeed::Instant } else { settings.game_speed };
+    if speed.0 != wanted {
+        speed.0 = wanted;
+    }
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        step_mode.enabled = !step_mode.enabled;
+        info!("Step mode {}", if step_mode.enabled { "on" } else { "off" });
+    }
+    if step_mode.enabled && keyboard_input.just_pressed(KeyCode::N) {
+        step_mode.step_requested = true;
+    }
+}
+
+/// Where to pick the turn back up when a saved game is restored. Taken
+/// instead of the usual `Idle -> RoundSetup` step.
+#[derive(Default)]
+pub struct ResumeTurn(pub Option<TurnState>);
+
+/// The only system that moves `TurnState` on outside of the player placing a
+/// tile. Runs once per turn step, and pest ticks wait until enough steps have
+/// gone by.
+fn progress_turn(
+    mut clock: ResMut<TurnClock>,
     mut keyboard_input_events: EventReader<KeyboardInput>,
-    mut state: ResMut<State<TurnState>>,
+    state: Res<State<TurnState>>,
+    mut transitions: ResMut<Transitions>,
+    mut resume_turn: ResMut<ResumeTurn>,
+    mut round_over: ResMut<RoundOver>,
+    run_over: Res<RunOver>,
+    mut last_round_end: ResMut<LastRoundEnd>,
+    mut events: EventWriter<GameEvent>,
+    speed: Res<TurnSpeed>,
+    mut step_mode: ResMut<StepMode>,
+    tween_query: Query<Entity, With<Tween>>,
 ) {
-    turn_timer.0.tick(time.delta());
+    clock.steps_since_tick += 1;
+    let ticked = match speed.0.tick_steps() {
+        Some(steps) => clock.steps_since_tick >= steps,
+        None => true,
+    };
     match state.current() {
-        TurnState::Idle => state.set(TurnState::RoundSetup).unwrap(),
-        TurnState::RoundSetup => state.set(TurnState::StartOfRound).unwrap(),
-        TurnState::StartOfRound => state.set(TurnState::PlayerTurn).unwrap(),
-        TurnState::EndOfRound => state.set(TurnState::RoundCleanup).unwrap(),
-        TurnState::RoundCleanup => state.set(TurnState::RoundSetup).unwrap(),
+        TurnState::Idle => match resume_turn.0.take() {
+            Some(TurnState::Idle) | None => transitions.set_turn(TurnState::RoundSetup),
+            Some(resume) => transitions.set_turn(resume),
+        },
+        TurnState::RoundSetup => transitions.set_turn(TurnState::StartOfRound),
+        TurnState::StartOfRound => transitions.set_turn(TurnState::PlayerTurn),
+        TurnState::EndOfRound => transitions.set_turn(TurnState::RoundCleanup),
+        TurnState::RoundCleanup => if run_over.0 {
+            transitions.set_game(GameState::PrizePlantScoring)
+        } else {
+            transitions.set_turn(TurnState::RoundSetup)
+        },
         TurnState::PestTurnA => {
-            if turn_timer.0.just_finished() {
-                state.set(TurnState::PestTurnB).unwrap()
+            if let Some(reason) = round_over.0.take() {
+                info!("Round over: {:?}", reason);
+                last_round_end.0 = Some(reason);
+                events.send(GameEvent::RoundEnded { reason });
+                transitions.set_turn(TurnState::EndOfRound)
+            } else if ticked {
+                clock.steps_since_tick = 0;
+                transitions.set_turn(TurnState::PestTurnB)
             }
         }
         TurnState::PestTurnB => {
-            if turn_timer.0.just_finished() {
-                state.set(TurnState::PestTurnA).unwrap()
+            // Let the pests finish moving on screen before they move again
+            let ready = if step_mode.enabled {
+                std::mem::take(&mut step_mode.step_requested)
+            } else {
+                ticked
+            };
+            if ready && tween_query.iter().next().is_none() {
+                clock.steps_since_tick = 0;
+                transitions.set_turn(TurnState::PestTurnA)
             }
         }
         _ => ()
     }
 }
 
+/// The steps counted through the player's turn don't go towards the first
+/// pest tick.
+fn start_pest_tick(
+    mut clock: ResMut<TurnClock>,
+) {
+    clock.steps_since_tick = 0;
+}
+
+fn announce_round_start(
+    mut events: EventWriter<GameEvent>,
+) {
+    events.send(GameEvent::RoundStarted);
+}
+
 fn reset_turn_state(
-    mut state: ResMut<State<TurnState>>,
+    mut transitions: ResMut<Transitions>,
+    mut clock: ResMut<TurnClock>,
+    mut round_over: ResMut<RoundOver>,
+    mut run_over: ResMut<RunOver>,
+    mut last_round_end: ResMut<LastRoundEnd>,
 ) {
-    state.set(TurnState::Idle);
+    *clock = TurnClock::default();
+    round_over.0 = None;
+    run_over.0 = false;
+    last_round_end.0 = None;
+    transitions.set_turn(TurnState::Idle);
 }
@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+use bevy::{
+    prelude::*,
+    input::touch::{TouchInput, TouchPhase},
+};
+use wasm_bindgen::{closure::Closure, JsCast};
+
+/// winit's web backend reports a finger on the canvas as the mouse, so taps
+/// never reach `track_touches` and there's no way to pinch or drag. This
+/// listens for the canvas's own touch events and passes them on as
+/// `TouchInput`, with the origin at the top left like native touches.
+pub struct WebTouchPlugin;
+
+impl Plugin for WebTouchPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<WebTouches>();
+        app.add_system_to_stage(CoreStage::PreUpdate, listen_for_touches.system().label("listen for touches"));
+        app.add_system_to_stage(CoreStage::PreUpdate, forward_touches.system().after("listen for touches"));
+    }
+}
+
+/// Touches the browser has reported since the last frame.
+#[derive(Clone, Default)]
+struct WebTouches(Arc<Mutex<Vec<TouchInput>>>);
+
+const TOUCH_EVENTS: [(&str, TouchPhase); 4] = [
+    ("touchstart", TouchPhase::Started),
+    ("touchmove", TouchPhase::Moved),
+    ("touchend", TouchPhase::Ended),
+    ("touchcancel", TouchPhase::Cancelled),
+];
+
+/// Adds the listeners once the canvas exists.
+fn listen_for_touches(
+    touches: Res<WebTouches>,
+    mut listening: Local<bool>,
+) {
+    if *listening {
+        return;
+    }
+    let canvas = match web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.query_selector("canvas").ok().flatten())
+    {
+        Some(canvas) => canvas,
+        None => return,
+    };
+    for (name, phase) in TOUCH_EVENTS.iter() {
+        let phase = *phase;
+        let touches = touches.clone();
+        let target = canvas.clone();
+        let listener = Closure::wrap(Box::new(move |event: web_sys::TouchEvent| {
+            let rect = target.get_bounding_client_rect();
+            let changed = event.changed_touches();
+            let mut touches = touches.0.lock().unwrap();
+            for i in 0..changed.length() {
+                if let Some(touch) = changed.get(i) {
+                    touches.push(TouchInput {
+                        phase,
+                        position: Vec2::new(
+                            (touch.client_x() as f64 - rect.left()) as f32,
+                            (touch.client_y() as f64 - rect.top()) as f32,
+                        ),
+                        force: None,
+                        id: touch.identifier() as u64,
+                    });
+                }
+            }
+        }) as Box<dyn FnMut(web_sys::TouchEvent)>);
+        if canvas.add_event_listener_with_callback(name, listener.as_ref().unchecked_ref()).is_err() {
+            warn!("Could not listen for {} on the canvas", name);
+        }
+        // Lives as long as the page
+        listener.forget();
+    }
+    *listening = true;
+}
+
+fn forward_touches(
+    touches: Res<WebTouches>,
+    mut touch_events: EventWriter<TouchInput>,
+) {
+    for touch in touches.0.lock().unwrap().drain(..) {
+        touch_events.send(touch);
+    }
+}